@@ -1,5 +1,6 @@
-use std::collections::VecDeque;
-use std::num::ParseIntError;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Write};
+use std::num::{ParseIntError, TryFromIntError};
 use std::str::FromStr;
 
 use thiserror::Error;
@@ -12,6 +13,22 @@ enum ParseError {
     InvalidNumber(#[from] ParseIntError),
 }
 
+#[allow(unused)]
+#[derive(Debug, Error)]
+enum AssembleError {
+    #[error("Line {line}: {source}")]
+    Syntax { line: usize, source: ParseError },
+    #[error("Line {line}: Unknown label {label:?}")]
+    UnknownLabel { line: usize, label: String },
+    #[error("Line {line}: Duplicate label {label:?}")]
+    DuplicateLabel { line: usize, label: String },
+    #[error("Line {line}: Jump offset out of range")]
+    OffsetOutOfRange {
+        line: usize,
+        source: TryFromIntError,
+    },
+}
+
 type Value = i32;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -26,12 +43,13 @@ impl FromStr for Instruction {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let (op, arg) = s.split_once(' ').ok_or(ParseError::SyntaxError)?;
-        Ok(match op {
-            "acc" => Self::Acc(arg.parse()?),
-            "nop" => Self::Nop(arg.parse()?),
-            "jmp" => Self::Jmp(arg.parse()?),
-            _ => return Err(ParseError::SyntaxError),
-        })
+        Self::new(op, arg.parse()?)
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {:+}", self.op(), self.arg())
     }
 }
 
@@ -40,70 +58,362 @@ fn parse(input: &str) -> Result<Vec<Instruction>, ParseError> {
     input.lines().map(str::parse).collect()
 }
 
-#[aoc(day8, part1)]
-fn part_1(instructions: &[Instruction]) -> Value {
+impl Instruction {
+    fn new(op: &str, arg: Value) -> Result<Self, ParseError> {
+        Ok(match op {
+            "acc" => Self::Acc(arg),
+            "nop" => Self::Nop(arg),
+            "jmp" => Self::Jmp(arg),
+            _ => return Err(ParseError::SyntaxError),
+        })
+    }
+
+    const fn op(self) -> &'static str {
+        match self {
+            Self::Acc(..) => "acc",
+            Self::Nop(..) => "nop",
+            Self::Jmp(..) => "jmp",
+        }
+    }
+
+    const fn arg(self) -> Value {
+        match self {
+            Self::Acc(x) | Self::Nop(x) | Self::Jmp(x) => x,
+        }
+    }
+
+    // Where the argument would send the instruction pointer if this was a
+    // `jmp`. Also used for `nop`, since it could be patched into a jump.
+    #[allow(unused)]
+    fn jump_target(self, ip: usize, len: usize) -> Option<usize> {
+        match self {
+            Self::Acc(..) => None,
+            Self::Nop(x) | Self::Jmp(x) => ip
+                .checked_add_signed(x as isize)
+                .filter(|&target| target <= len),
+        }
+    }
+
+    const fn target(self, ip: usize, len: usize) -> usize {
+        match self {
+            Self::Acc(..) | Self::Nop(..) => ip + 1,
+            Self::Jmp(x) => match ip.checked_add_signed(x as isize) {
+                Some(target) if target < len => target,
+                _ => len,
+            },
+        }
+    }
+
+    fn patches(self, rules: PatchRules) -> impl Iterator<Item = Self> {
+        let swapped = match self {
+            Self::Nop(x) if rules.swap_jmp_nop => Some(Self::Jmp(x)),
+            Self::Jmp(x) if rules.swap_jmp_nop => Some(Self::Nop(x)),
+            _ => None,
+        };
+        let disabled = match self {
+            Self::Acc(x) if rules.acc_to_nop => Some(Self::Nop(x)),
+            _ => None,
+        };
+        swapped.into_iter().chain(disabled)
+    }
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand<'a> {
+    Offset(Value),
+    Label(&'a str),
+}
+
+#[allow(unused)]
+impl<'a> Operand<'a> {
+    fn parse(s: &'a str) -> Result<Self, ParseError> {
+        if s.starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '_') {
+            if s.chars().all(|ch| ch.is_ascii_alphanumeric() || ch == '_') {
+                Ok(Self::Label(s))
+            } else {
+                Err(ParseError::SyntaxError)
+            }
+        } else {
+            Ok(Self::Offset(s.parse()?))
+        }
+    }
+}
+
+// Assembles source with labels and comments into a program. Each line holds an
+// optional `label:`, an optional instruction whose argument is either a
+// relative offset or a label, and an optional `#` comment. Plain puzzle input
+// assembles to the same program as `parse`.
+#[allow(unused)]
+fn assemble(source: &str) -> Result<Vec<Instruction>, AssembleError> {
+    let mut labels = HashMap::new();
+    let mut pending = Vec::new();
+    for (line_ix, line) in source.lines().enumerate() {
+        let line_no = line_ix + 1;
+        let syntax = |source| AssembleError::Syntax {
+            line: line_no,
+            source,
+        };
+        let mut code = line.split_once('#').map_or(line, |(code, _)| code).trim();
+        if let Some((label, rest)) = code.split_once(':') {
+            let label = label.trim();
+            if !matches!(Operand::parse(label), Ok(Operand::Label(_))) {
+                return Err(syntax(ParseError::SyntaxError));
+            }
+            if labels.insert(label, pending.len()).is_some() {
+                return Err(AssembleError::DuplicateLabel {
+                    line: line_no,
+                    label: label.to_string(),
+                });
+            }
+            code = rest.trim();
+        }
+        if code.is_empty() {
+            continue;
+        }
+        let (op, arg) = code
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| syntax(ParseError::SyntaxError))?;
+        let operand = Operand::parse(arg.trim()).map_err(syntax)?;
+        // Validate the opcode now, so errors are reported in source order
+        Instruction::new(op, 0).map_err(syntax)?;
+        pending.push((line_no, op, operand));
+    }
+    pending
+        .iter()
+        .enumerate()
+        .map(|(ip, &(line, op, operand))| {
+            let arg = match operand {
+                Operand::Offset(offset) => offset,
+                Operand::Label(label) => {
+                    let &target = labels
+                        .get(label)
+                        .ok_or_else(|| AssembleError::UnknownLabel {
+                            line,
+                            label: label.to_string(),
+                        })?;
+                    let offset_error = |source| AssembleError::OffsetOutOfRange { line, source };
+                    Value::try_from(target).map_err(offset_error)?
+                        - Value::try_from(ip).map_err(offset_error)?
+                }
+            };
+            Instruction::new(op, arg).map_err(|source| AssembleError::Syntax { line, source })
+        })
+        .collect()
+}
+
+// Lists the program with a synthesized `L<ip>` label at every jump target,
+// with the address and original offset of each instruction in a comment. The
+// listing assembles back to the same program.
+#[allow(unused)]
+fn disassemble(instructions: &[Instruction]) -> String {
+    let len = instructions.len();
+    let mut is_target = vec![false; len + 1];
+    for (ip, instr) in instructions.iter().enumerate() {
+        if let Some(target) = instr.jump_target(ip, len) {
+            is_target[target] = true;
+        }
+    }
+    let mut listing = String::new();
+    for (ip, &instr) in instructions.iter().enumerate() {
+        if is_target[ip] {
+            writeln!(listing, "L{ip}:").unwrap();
+        }
+        if let Some(target) = instr.jump_target(ip, len) {
+            let text = format!("{} L{target}", instr.op());
+            writeln!(listing, "    {text:<12}# {ip:>3} ({:+})", instr.arg()).unwrap();
+        } else {
+            writeln!(listing, "    {:<12}# {ip:>3}", instr.to_string()).unwrap();
+        }
+    }
+    if is_target[len] {
+        writeln!(listing, "L{len}:").unwrap();
+    }
+    listing
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PatchRules {
+    swap_jmp_nop: bool,
+    acc_to_nop: bool,
+}
+
+impl Default for PatchRules {
+    fn default() -> Self {
+        Self {
+            swap_jmp_nop: true,
+            acc_to_nop: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Patch {
+    ip: usize,
+    replacement: Instruction,
+}
+
+fn apply_patches(instructions: &[Instruction], patches: &[Patch]) -> Vec<Instruction> {
+    let mut program = instructions.to_vec();
+    for patch in patches {
+        program[patch.ip] = patch.replacement;
+    }
+    program
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Execution {
+    accumulator: Value,
+    terminated: bool,
+}
+
+fn execute(instructions: &[Instruction]) -> Execution {
     let mut accumulator = 0;
     let mut ip = 0;
     let mut visited = vec![false; instructions.len()];
     while let Some(&instr) = instructions.get(ip) {
         if visited[ip] {
-            return accumulator;
+            return Execution {
+                accumulator,
+                terminated: false,
+            };
         }
         visited[ip] = true;
-        match instr {
-            Instruction::Acc(x) => accumulator += x,
-            Instruction::Nop(..) => (),
-            Instruction::Jmp(x) => {
-                ip = ip
-                    .checked_add_signed(x as isize)
-                    .unwrap_or(instructions.len());
-                continue;
-            }
+        if let Instruction::Acc(x) = instr {
+            accumulator += x;
         }
-        ip += 1;
+        ip = instr.target(ip, instructions.len());
+    }
+    Execution {
+        accumulator,
+        terminated: true,
     }
-    accumulator
 }
 
-#[aoc(day8, part2)]
-fn part_2(instructions: &[Instruction]) -> Value {
-    let mut pending = VecDeque::new();
-    let n = instructions.len();
-    let mut visited = vec![false; n * (n + 1)];
-    pending.push_back((0, None, 0));
-    while let Some((ip, switched, accum)) = pending.pop_front() {
-        if ip >= instructions.len() {
-            return accum;
-        }
-        if visited[switched.unwrap_or(n) * n + ip] {
-            continue;
+// Every instruction has exactly one successor, with all jumps out of the
+// program collapsed into the exit node at `instructions.len()`. Walking the
+// predecessor edges backwards from the exit finds every instruction that
+// eventually terminates.
+#[allow(unused)]
+#[derive(Debug, Clone)]
+struct ControlFlow {
+    terminates: Vec<bool>,
+}
+
+#[allow(unused)]
+impl ControlFlow {
+    fn new(instructions: &[Instruction]) -> Self {
+        let len = instructions.len();
+        let mut predecessors = vec![Vec::new(); len + 1];
+        for (ip, instr) in instructions.iter().enumerate() {
+            predecessors[instr.target(ip, len)].push(ip);
         }
-        visited[switched.unwrap_or(n) * n + ip] = true;
-        match instructions[ip] {
-            Instruction::Acc(x) => {
-                pending.push_back((ip + 1, switched, accum + x));
-            }
-            Instruction::Jmp(x) => {
-                let ip2 = ip
-                    .checked_add_signed(x as isize)
-                    .unwrap_or(instructions.len());
-                pending.push_back((ip2, switched, accum));
-                if switched.is_none() {
-                    pending.push_back((ip + 1, Some(ip), accum));
+        let mut terminates = vec![false; len + 1];
+        terminates[len] = true;
+        let mut pending = vec![len];
+        while let Some(ip) = pending.pop() {
+            for &prev in &predecessors[ip] {
+                if !terminates[prev] {
+                    terminates[prev] = true;
+                    pending.push(prev);
                 }
             }
-            Instruction::Nop(x) => {
-                pending.push_back((ip + 1, switched, accum));
-                if switched.is_none() {
-                    let ip2 = ip
-                        .checked_add_signed(x as isize)
-                        .unwrap_or(instructions.len());
-                    pending.push_back((ip2, Some(ip), accum));
+        }
+        Self { terminates }
+    }
+
+    fn terminates(&self, ip: usize) -> bool {
+        self.terminates[ip]
+    }
+}
+
+// All single instruction patches that make a looping program terminate. A
+// program that already terminates needs no fix, and gets none.
+#[allow(unused)]
+fn single_patch_fixes(instructions: &[Instruction], rules: PatchRules) -> Vec<Patch> {
+    let flow = ControlFlow::new(instructions);
+    let len = instructions.len();
+    let mut fixes = Vec::new();
+    if flow.terminates(0) {
+        return fixes;
+    }
+    // Only the instructions on the original path can affect the outcome. Since
+    // none of them terminate, no terminating path from a patched target can
+    // pass back through the patched instruction.
+    let mut visited = vec![false; len];
+    let mut ip = 0;
+    while ip < len && !visited[ip] {
+        visited[ip] = true;
+        let instr = instructions[ip];
+        for replacement in instr.patches(rules) {
+            if flow.terminates(replacement.target(ip, len)) {
+                fixes.push(Patch { ip, replacement });
+            }
+        }
+        ip = instr.target(ip, len);
+    }
+    fixes
+}
+
+// Fewest patches needed to make the program terminate, found with a 0-1 BFS
+// where following an instruction costs nothing and patching it costs one. A
+// terminating run never visits an instruction twice, so the shortest path
+// through the graph is exactly the execution of the patched program.
+fn minimal_repair(instructions: &[Instruction], rules: PatchRules) -> Option<Vec<Patch>> {
+    let len = instructions.len();
+    let mut cost = vec![usize::MAX; len + 1];
+    let mut came_from = vec![None; len + 1];
+    let mut pending = VecDeque::new();
+    cost[0] = 0;
+    pending.push_back(0);
+    while let Some(ip) = pending.pop_front() {
+        if ip == len {
+            break;
+        }
+        let instr = instructions[ip];
+        let edges = std::iter::once((instr, 0)).chain(instr.patches(rules).map(|r| (r, 1)));
+        for (replacement, extra) in edges {
+            let next = replacement.target(ip, len);
+            let next_cost = cost[ip] + extra;
+            if next_cost < cost[next] {
+                cost[next] = next_cost;
+                came_from[next] = Some((ip, (extra != 0).then_some(replacement)));
+                if extra == 0 {
+                    pending.push_front(next);
+                } else {
+                    pending.push_back(next);
                 }
             }
         }
     }
-    0
+    if cost[len] == usize::MAX {
+        return None;
+    }
+    let mut patches = Vec::new();
+    let mut ip = len;
+    while let Some((prev, replacement)) = came_from[ip] {
+        if let Some(replacement) = replacement {
+            patches.push(Patch {
+                ip: prev,
+                replacement,
+            });
+        }
+        ip = prev;
+    }
+    patches.reverse();
+    Some(patches)
+}
+
+#[aoc(day8, part1)]
+fn part_1(instructions: &[Instruction]) -> Value {
+    execute(instructions).accumulator
+}
+
+#[aoc(day8, part2)]
+fn part_2(instructions: &[Instruction]) -> Value {
+    minimal_repair(instructions, PatchRules::default()).map_or(0, |patches| {
+        execute(&apply_patches(instructions, &patches)).accumulator
+    })
 }
 
 #[cfg(test)]
@@ -154,4 +464,164 @@ mod tests {
         let result = part_2(&instruction);
         assert_eq!(result, 8);
     }
+
+    #[test]
+    fn test_display() {
+        let instructions = parse(EXAMPLE).unwrap();
+        let text = instructions
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(text, EXAMPLE);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let instructions = parse(EXAMPLE).unwrap();
+        let listing = disassemble(&instructions);
+        assert_eq!(
+            listing,
+            "\
+            L0:\n    \
+                nop L0      #   0 (+0)\n\
+            L1:\n    \
+                acc +1      #   1\n    \
+                jmp L6      #   2 (+4)\n\
+            L3:\n    \
+                acc +3      #   3\n    \
+                jmp L1      #   4 (-3)\n    \
+                acc -99     #   5\n\
+            L6:\n    \
+                acc +1      #   6\n    \
+                jmp L3      #   7 (-4)\n    \
+                acc +6      #   8\n\
+            "
+        );
+        assert_eq!(assemble(&listing).unwrap(), instructions);
+    }
+
+    #[test]
+    fn test_disassemble_out_of_range() {
+        let instructions = parse("jmp +2\nnop -5\njmp +7").unwrap();
+        let listing = disassemble(&instructions);
+        assert_eq!(
+            listing,
+            "\
+            \x20   jmp L2      #   0 (+2)\n    \
+                nop -5      #   1\n\
+            L2:\n    \
+                jmp +7      #   2\n\
+            "
+        );
+        assert_eq!(assemble(&listing).unwrap(), instructions);
+    }
+
+    #[test]
+    fn test_assemble() {
+        assert_eq!(assemble(EXAMPLE).unwrap(), parse(EXAMPLE).unwrap());
+        let source = "\
+            # Count down from the top\n\
+            start: acc +3\n\
+            \n\
+            loop:\n\
+            \x20   nop end # Never taken\n\
+            \x20   jmp loop\n\
+            end:\
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [
+                Instruction::Acc(3),
+                Instruction::Nop(2),
+                Instruction::Jmp(-1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        assert!(matches!(
+            assemble("acc +1\njmp nowhere"),
+            Err(AssembleError::UnknownLabel { line: 2, .. })
+        ));
+        assert!(matches!(
+            assemble("a: acc +1\na: jmp a"),
+            Err(AssembleError::DuplicateLabel { line: 2, .. })
+        ));
+        assert!(matches!(
+            assemble("acc +1\nmul +2"),
+            Err(AssembleError::Syntax { line: 2, .. })
+        ));
+        assert!(matches!(
+            assemble("jmp 1x"),
+            Err(AssembleError::Syntax { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let instructions = parse(EXAMPLE).unwrap();
+        let flow = ControlFlow::new(&instructions);
+        let terminating = (0..=instructions.len())
+            .filter(|&ip| flow.terminates(ip))
+            .collect::<Vec<_>>();
+        assert_eq!(terminating, [8, 9]);
+    }
+
+    #[test]
+    fn test_single_patch_fixes() {
+        let instructions = parse(EXAMPLE).unwrap();
+        let result = single_patch_fixes(&instructions, PatchRules::default());
+        assert_eq!(
+            result,
+            [Patch {
+                ip: 7,
+                replacement: Instruction::Nop(-4)
+            }]
+        );
+        let patched = apply_patches(&instructions, &result);
+        assert!(single_patch_fixes(&patched, PatchRules::default()).is_empty());
+    }
+
+    #[test]
+    fn test_single_patch_fixes_acc_to_nop() {
+        let instructions = parse(EXAMPLE).unwrap();
+        let rules = PatchRules {
+            swap_jmp_nop: false,
+            acc_to_nop: true,
+        };
+        assert!(single_patch_fixes(&instructions, rules).is_empty());
+    }
+
+    #[test]
+    fn test_minimal_repair() {
+        let instructions = parse("jmp +0\njmp +0\nacc +1\njmp -3").unwrap();
+        let result = minimal_repair(&instructions, PatchRules::default()).unwrap();
+        assert_eq!(
+            result,
+            [
+                Patch {
+                    ip: 0,
+                    replacement: Instruction::Nop(0)
+                },
+                Patch {
+                    ip: 1,
+                    replacement: Instruction::Nop(0)
+                },
+                Patch {
+                    ip: 3,
+                    replacement: Instruction::Nop(-3)
+                },
+            ]
+        );
+        let execution = execute(&apply_patches(&instructions, &result));
+        assert_eq!(
+            execution,
+            Execution {
+                accumulator: 1,
+                terminated: true
+            }
+        );
+    }
 }
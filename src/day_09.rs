@@ -1,67 +1,148 @@
-use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::num::ParseIntError;
+use std::ops::{Add, Range};
 
 #[aoc_generator(day9)]
 fn parse(input: &str) -> Result<Vec<u64>, ParseIntError> {
     input.lines().map(str::parse).collect()
 }
 
+const PREAMBLE: usize = 25;
+
 #[aoc(day9, part1)]
-fn part_1(xmas: &[u64]) -> u64 {
-    find_invalid_number(xmas, 25)
+fn part_1(xmas: &[u64]) -> Option<u64> {
+    find_invalid_number(xmas, PREAMBLE)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct InvalidNumber {
+    index: usize,
+    value: u64,
 }
 
-fn find_invalid_number(xmas: &[u64], preamble: usize) -> u64 {
-    let mut counts = HashMap::<u64, u8>::new();
-    for (ix, &x) in xmas[..preamble].iter().enumerate() {
-        for &y in &xmas[..ix] {
-            *counts.entry(x + y).or_default() += 1;
+// Validates one number at a time against the sums of all pairs in the window of
+// the last `preamble` numbers. Numbers in the preamble itself are always valid.
+#[derive(Debug, Clone)]
+struct Validator {
+    preamble: usize,
+    window: VecDeque<u64>,
+    pair_sums: HashMap<u64, usize>,
+    index: usize,
+}
+
+impl Validator {
+    fn new(preamble: usize) -> Self {
+        Self {
+            preamble,
+            window: VecDeque::with_capacity(preamble + 1),
+            pair_sums: HashMap::new(),
+            index: 0,
         }
     }
-    for (window, &add) in xmas.windows(preamble).zip(&xmas[preamble..]) {
-        if counts.get(&add).is_none_or(|&cnt| cnt == 0) {
-            return add;
+
+    fn push(&mut self, value: u64) -> Option<InvalidNumber> {
+        let index = self.index;
+        self.index += 1;
+        let is_valid = self.window.len() < self.preamble
+            || self.pair_sums.get(&value).is_some_and(|&count| count > 0);
+        for &y in &self.window {
+            if let Some(sum) = value.checked_add(y) {
+                *self.pair_sums.entry(sum).or_default() += 1;
+            }
         }
-        let x = window[0];
-        for &y in &window[1..] {
-            counts.entry(x + y).and_modify(|count| *count -= 1);
-            *counts.entry(add + y).or_default() += 1;
+        self.window.push_back(value);
+        if self.window.len() > self.preamble
+            && let Some(x) = self.window.pop_front()
+        {
+            for &y in &self.window {
+                if let Some(sum) = x.checked_add(y)
+                    && let Some(count) = self.pair_sums.get_mut(&sum)
+                {
+                    *count -= 1;
+                }
+            }
         }
+        (!is_valid).then_some(InvalidNumber { index, value })
     }
-    0
+}
+
+fn invalid_numbers(
+    xmas: impl IntoIterator<Item = u64>,
+    preamble: usize,
+) -> impl Iterator<Item = InvalidNumber> {
+    let mut validator = Validator::new(preamble);
+    xmas.into_iter()
+        .filter_map(move |value| validator.push(value))
+}
+
+fn find_invalid_number(xmas: &[u64], preamble: usize) -> Option<u64> {
+    invalid_numbers(xmas.iter().copied(), preamble)
+        .next()
+        .map(|invalid| invalid.value)
 }
 
 #[aoc(day9, part2)]
-fn part_2(xmas: &[u64]) -> u64 {
-    let invalid = find_invalid_number(xmas, 25);
+fn part_2(xmas: &[u64]) -> Option<u64> {
+    let invalid = find_invalid_number(xmas, PREAMBLE)?;
     find_subsequence_with_sum(xmas, invalid)
 }
 
-fn find_subsequence_with_sum(xmas: &[u64], target_sum: u64) -> u64 {
-    let mut left = 0;
-    let mut right = 0;
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Weakness<R> {
+    range: Range<usize>,
+    value: R,
+}
+
+// All ranges of at least two numbers that add up to `target`, ordered by start
+// and then end. Works on signed values, by looking up earlier prefix sums that
+// differ from the current prefix sum by exactly `target`.
+fn contiguous_ranges_with_sum<T: Copy + Into<i128>>(values: &[T], target: T) -> Vec<Range<usize>> {
+    let target = target.into();
+    let mut prefix_sums = Vec::with_capacity(values.len() + 1);
     let mut sum = 0;
-    loop {
-        match sum.cmp(&target_sum) {
-            Ordering::Less => {
-                if right >= xmas.len() {
-                    // Could not find a subsequence that adds to `target_sum`
-                    return 0;
-                }
-                sum += xmas[right];
-                right += 1;
-            }
-            Ordering::Greater => {
-                sum -= xmas[left];
-                left += 1;
-            }
-            Ordering::Equal => {
-                return xmas[left..right].iter().copied().min().unwrap()
-                    + xmas[left..right].iter().copied().max().unwrap();
-            }
+    prefix_sums.push(sum);
+    for &value in values {
+        sum += value.into();
+        prefix_sums.push(sum);
+    }
+    let mut starts = HashMap::<i128, Vec<usize>>::new();
+    let mut ranges = Vec::new();
+    for end in 2..prefix_sums.len() {
+        starts
+            .entry(prefix_sums[end - 2])
+            .or_default()
+            .push(end - 2);
+        if let Some(found) = starts.get(&(prefix_sums[end] - target)) {
+            ranges.extend(found.iter().map(|&start| start..end));
         }
     }
+    ranges.sort_unstable_by_key(|range| (range.start, range.end));
+    ranges
+}
+
+fn encryption_weaknesses<T: Copy + Into<i128>, R>(
+    values: &[T],
+    target: T,
+    reduce: impl Fn(&[T]) -> R,
+) -> Vec<Weakness<R>> {
+    contiguous_ranges_with_sum(values, target)
+        .into_iter()
+        .map(|range| Weakness {
+            value: reduce(&values[range.clone()]),
+            range,
+        })
+        .collect()
+}
+
+fn min_plus_max<T: Copy + Ord + Add<Output = T>>(values: &[T]) -> T {
+    values.iter().copied().min().unwrap() + values.iter().copied().max().unwrap()
+}
+
+fn find_subsequence_with_sum(xmas: &[u64], target_sum: u64) -> Option<u64> {
+    encryption_weaknesses(xmas, target_sum, min_plus_max)
+        .into_iter()
+        .next()
+        .map(|weakness| weakness.value)
 }
 
 #[cfg(test)]
@@ -95,12 +176,73 @@ mod tests {
     fn test_find_invalid_number() {
         let xmas = parse(EXAMPLE).unwrap();
         let result = find_invalid_number(&xmas, 5);
-        assert_eq!(result, 127);
+        assert_eq!(result, Some(127));
+    }
+
+    #[test]
+    fn test_find_invalid_number_none() {
+        let xmas = parse(EXAMPLE).unwrap();
+        let result = find_invalid_number(&xmas[..14], 5);
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_invalid_numbers() {
+        let result = invalid_numbers([1, 2, 3, 4, 10, 7, 5, 100], 3).collect::<Vec<_>>();
+        assert_eq!(
+            result,
+            [
+                InvalidNumber {
+                    index: 4,
+                    value: 10
+                },
+                InvalidNumber { index: 6, value: 5 },
+                InvalidNumber {
+                    index: 7,
+                    value: 100
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validator_streaming() {
+        let mut validator = Validator::new(2);
+        assert_eq!(validator.push(1), None);
+        assert_eq!(validator.push(2), None);
+        assert_eq!(validator.push(3), None);
+        assert_eq!(
+            validator.push(4),
+            Some(InvalidNumber { index: 3, value: 4 })
+        );
+        assert_eq!(validator.push(7), None);
     }
     #[test]
     fn test_find_subsequence_with_sum() {
         let xmas = parse(EXAMPLE).unwrap();
         let result = find_subsequence_with_sum(&xmas, 127);
-        assert_eq!(result, 62);
+        assert_eq!(result, Some(62));
+        let result = find_subsequence_with_sum(&xmas, 1);
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_contiguous_ranges_with_sum_signed() {
+        let values: [i64; 7] = [3, -1, 2, -2, 4, 0, -4];
+        let result = contiguous_ranges_with_sum(&values, 2);
+        assert_eq!(result, [0..2, 0..4, 0..7, 3..5, 3..6]);
+    }
+
+    #[test]
+    fn test_encryption_weaknesses() {
+        let xmas = parse(EXAMPLE).unwrap();
+        let result = encryption_weaknesses(&xmas, 127, <[u64]>::len);
+        assert_eq!(
+            result,
+            [Weakness {
+                range: 2..6,
+                value: 4
+            }]
+        );
     }
 }
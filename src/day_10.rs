@@ -1,4 +1,9 @@
-use std::num::ParseIntError;
+use std::collections::BTreeMap;
+use std::num::{NonZeroU32, ParseIntError};
+
+use num_bigint::BigUint;
+use num_traits::Zero;
+use thiserror::Error;
 
 #[aoc_generator(day10)]
 fn parse(input: &str) -> Result<Vec<u32>, ParseIntError> {
@@ -7,36 +12,213 @@ fn parse(input: &str) -> Result<Vec<u32>, ParseIntError> {
         .map(str::parse)
         .collect::<Result<Vec<_>, _>>()?;
     result.push(0);
-    result.push(result.iter().copied().max().unwrap() + 3);
+    result.push(result.iter().copied().max().unwrap_or(0) + 3);
     result.sort_unstable();
     Ok(result)
 }
 
+const MAX_GAP: u32 = 3;
+
 #[aoc(day10, part1)]
-fn part_1(voltage: &[u32]) -> u32 {
-    let mut diff_counts = [0; 4];
-    for (&v1, &v2) in voltage.iter().zip(&voltage[1..]) {
-        let dv = v2 - v1;
-        diff_counts[dv as usize] += 1;
+fn part_1(voltage: &[u32]) -> usize {
+    let diff_counts = joltage_differences(voltage);
+    let count = |diff| diff_counts.get(&diff).copied().unwrap_or(0);
+    count(1) * count(3)
+}
+
+#[aoc(day10, part2)]
+fn part_2(voltage: &[u32]) -> BigUint {
+    Arrangements::new(voltage, MAX_GAP).count().clone()
+}
+
+fn joltage_differences(chain: &[u32]) -> BTreeMap<u32, usize> {
+    let mut diff_counts = BTreeMap::new();
+    for (&v1, &v2) in chain.iter().zip(&chain[1..]) {
+        *diff_counts.entry(v2 - v1).or_default() += 1;
     }
+    diff_counts
+}
 
-    println!("{diff_counts:?}");
+#[allow(unused)]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+enum ChainError {
+    #[error("Adapter rating {joltage} appears {count} times, starting at index {index}")]
+    Duplicate {
+        index: usize,
+        joltage: u32,
+        count: usize,
+    },
+    #[error("Chain breaks after index {index}, between {from} and {to} jolts")]
+    Gap { index: usize, from: u32, to: u32 },
+}
 
-    diff_counts[1] * diff_counts[3]
+// Everything that stops a sorted chain from using every adapter, in chain order.
+#[allow(unused)]
+fn validate_chain(chain: &[u32], max_gap: u32) -> Vec<ChainError> {
+    let mut errors = Vec::new();
+    let mut index = 0;
+    for group in chain.chunk_by(|a, b| a == b) {
+        if group.len() > 1 {
+            errors.push(ChainError::Duplicate {
+                index,
+                joltage: group[0],
+                count: group.len(),
+            });
+        }
+        index += group.len();
+        if let Some(&to) = chain.get(index)
+            && to - group[0] > max_gap
+        {
+            errors.push(ChainError::Gap {
+                index: index - 1,
+                from: group[0],
+                to,
+            });
+        }
+    }
+    errors
 }
 
-#[aoc(day10, part2)]
-fn part_2(voltage: &[u32]) -> u64 {
-    let mut dp = vec![0; voltage.len()];
-    dp[0] = 1;
-    let mut left = 0;
-    for (right, &v) in voltage.iter().enumerate().skip(1) {
-        while voltage[left] + 3 < v {
-            left += 1;
+// The fewest adapter ratings to add so that every gap is at most `max_gap`.
+// Duplicates can not be fixed by adding adapters, and are ignored. A gap of 0
+// could never be closed, so it is ruled out by the type.
+#[allow(unused)]
+fn suggest_adapters(chain: &[u32], max_gap: NonZeroU32) -> Vec<u32> {
+    let max_gap = max_gap.get();
+    let mut suggestions = Vec::new();
+    for (&from, &to) in chain.iter().zip(&chain[1..]) {
+        if to - from > max_gap {
+            suggestions.extend((from + max_gap..to).step_by(max_gap as usize));
         }
-        dp[right] = dp[left..right].iter().copied().sum();
     }
-    dp.last().copied().unwrap()
+    suggestions
+}
+
+// Every way to get from the first to the last joltage of a sorted chain,
+// stepping at most `max_gap` jolts at a time. Arrangements are ordered by
+// always preferring the nearest next adapter, and can be enumerated in that
+// order, or picked out by index for sampling.
+#[allow(unused)]
+#[derive(Debug, Clone)]
+struct Arrangements<'a> {
+    chain: &'a [u32],
+    max_gap: u32,
+    ways_from: Vec<BigUint>,
+}
+
+impl<'a> Arrangements<'a> {
+    fn new(chain: &'a [u32], max_gap: u32) -> Self {
+        let mut ways_from = vec![BigUint::ZERO; chain.len()];
+        if let Some(last) = ways_from.last_mut() {
+            *last = BigUint::from(1u8);
+        }
+        let mut right = chain.len();
+        for left in (0..chain.len().saturating_sub(1)).rev() {
+            while chain[right - 1] - chain[left] > max_gap {
+                right -= 1;
+            }
+            let ways = ways_from[left + 1..right].iter().sum();
+            ways_from[left] = ways;
+        }
+        Self {
+            chain,
+            max_gap,
+            ways_from,
+        }
+    }
+
+    fn count(&self) -> &BigUint {
+        static ZERO: BigUint = BigUint::ZERO;
+        self.ways_from.first().unwrap_or(&ZERO)
+    }
+}
+
+#[allow(unused)]
+impl Arrangements<'_> {
+    // The nearest adapter after `after` that can be reached from `from` and
+    // still leads to the end of the chain.
+    fn next_step(&self, from: usize, after: usize) -> Option<usize> {
+        (after + 1..self.chain.len())
+            .take_while(|&ix| self.chain[ix] - self.chain[from] <= self.max_gap)
+            .find(|&ix| !self.ways_from[ix].is_zero())
+    }
+
+    fn complete(&self, path: &mut Vec<usize>) {
+        while let Some(&last) = path.last()
+            && let Some(next) = self.next_step(last, last)
+        {
+            path.push(next);
+        }
+    }
+
+    fn joltages(&self, path: &[usize]) -> Vec<u32> {
+        path.iter().map(|&ix| self.chain[ix]).collect()
+    }
+
+    fn nth(&self, index: &BigUint) -> Option<Vec<u32>> {
+        if index >= self.count() {
+            return None;
+        }
+        let mut index = index.clone();
+        let mut path = vec![0];
+        let mut from = 0;
+        while from + 1 < self.chain.len() {
+            let mut candidate = from;
+            loop {
+                candidate = self.next_step(from, candidate)?;
+                let ways = &self.ways_from[candidate];
+                if index < *ways {
+                    break;
+                }
+                index -= ways;
+            }
+            path.push(candidate);
+            from = candidate;
+        }
+        Some(self.joltages(&path))
+    }
+
+    const fn iter(&self) -> ArrangementIter<'_> {
+        ArrangementIter {
+            arrangements: self,
+            path: Vec::new(),
+            started: false,
+        }
+    }
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone)]
+struct ArrangementIter<'a> {
+    arrangements: &'a Arrangements<'a>,
+    path: Vec<usize>,
+    started: bool,
+}
+
+impl Iterator for ArrangementIter<'_> {
+    type Item = Vec<u32>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.started {
+            // Backtrack to the last adapter that has an alternative
+            loop {
+                let last = self.path.pop()?;
+                let &prev = self.path.last()?;
+                if let Some(next) = self.arrangements.next_step(prev, last) {
+                    self.path.push(next);
+                    break;
+                }
+            }
+        } else {
+            self.started = true;
+            if self.arrangements.count().is_zero() {
+                return None;
+            }
+            self.path.push(0);
+        }
+        self.arrangements.complete(&mut self.path);
+        Some(self.arrangements.joltages(&self.path))
+    }
 }
 
 #[cfg(test)]
@@ -94,15 +276,128 @@ mod tests {
 
     #[test_case(EXAMPLE1 => 35)]
     #[test_case(EXAMPLE2 => 220)]
-    fn test_part_1(input: &str) -> u32 {
+    fn test_part_1(input: &str) -> usize {
         let voltages = parse(input).unwrap();
         part_1(&voltages)
     }
 
-    #[test_case(EXAMPLE1 => 8)]
-    #[test_case(EXAMPLE2 => 19208)]
-    fn test_part_2(input: &str) -> u64 {
+    #[test_case(EXAMPLE1 => BigUint::from(8u32))]
+    #[test_case(EXAMPLE2 => BigUint::from(19208u32))]
+    fn test_part_2(input: &str) -> BigUint {
         let voltages = parse(input).unwrap();
         part_2(&voltages)
     }
+
+    #[test_case(EXAMPLE1 => [(1, 7), (3, 5)])]
+    #[test_case(EXAMPLE2 => [(1, 22), (3, 10)])]
+    fn test_joltage_differences(input: &str) -> [(u32, usize); 2] {
+        let voltages = parse(input).unwrap();
+        let histogram = joltage_differences(&voltages);
+        histogram
+            .into_iter()
+            .collect::<Vec<_>>()
+            .try_into()
+            .unwrap()
+    }
+
+    #[test_case(EXAMPLE1, 2 => 0)]
+    #[test_case(EXAMPLE1, 3 => 8)]
+    #[test_case(EXAMPLE1, 4 => 170)]
+    fn test_count_max_gap(input: &str, max_gap: u32) -> u32 {
+        let voltages = parse(input).unwrap();
+        let arrangements = Arrangements::new(&voltages, max_gap);
+        arrangements.count().try_into().unwrap()
+    }
+
+    const GAP: NonZeroU32 = NonZeroU32::new(3).unwrap();
+
+    #[test_case(EXAMPLE1)]
+    #[test_case(EXAMPLE2)]
+    fn test_validate_valid_chain(input: &str) {
+        let voltages = parse(input).unwrap();
+        assert_eq!(validate_chain(&voltages, 3), []);
+        assert_eq!(suggest_adapters(&voltages, GAP), []);
+    }
+
+    #[test]
+    fn test_validate_broken_chain() {
+        let voltages = parse("1\n2\n2\n9").unwrap();
+        assert_eq!(
+            validate_chain(&voltages, 3),
+            [
+                ChainError::Duplicate {
+                    index: 2,
+                    joltage: 2,
+                    count: 2
+                },
+                ChainError::Gap {
+                    index: 3,
+                    from: 2,
+                    to: 9
+                },
+            ]
+        );
+        assert_eq!(suggest_adapters(&voltages, GAP), [5, 8]);
+    }
+
+    #[test_case("6" => vec![3])]
+    #[test_case("10" => vec![3, 6, 9])]
+    #[test_case("" => Vec::<u32>::new())]
+    fn test_suggest_adapters(input: &str) -> Vec<u32> {
+        let voltages = parse(input).unwrap();
+        let suggestions = suggest_adapters(&voltages, GAP);
+        let mut fixed = voltages;
+        fixed.extend(&suggestions);
+        fixed.sort_unstable();
+        assert_eq!(validate_chain(&fixed, 3), []);
+        suggestions
+    }
+
+    #[test]
+    fn test_suggest_adapters_smallest_gap() {
+        let chain = [0, 4];
+        assert_eq!(suggest_adapters(&chain, NonZeroU32::MIN), [1, 2, 3]);
+        assert_eq!(
+            validate_chain(&chain, 0),
+            [ChainError::Gap {
+                index: 0,
+                from: 0,
+                to: 4
+            }]
+        );
+    }
+
+    #[test]
+    fn test_count_exceeds_u64() {
+        let mut chain = (0..=100).collect::<Vec<_>>();
+        chain.push(103);
+        let arrangements = Arrangements::new(&chain, 3);
+        assert_eq!(
+            arrangements.count().to_string(),
+            "180396380815100901214157639"
+        );
+    }
+
+    #[test]
+    fn test_enumerate_arrangements() {
+        let voltages = parse(EXAMPLE1).unwrap();
+        let arrangements = Arrangements::new(&voltages, 3);
+        let all = arrangements.iter().collect::<Vec<_>>();
+        assert_eq!(all.len(), 8);
+        assert_eq!(all[0], voltages);
+        assert_eq!(all[7], [0, 1, 4, 7, 10, 12, 15, 16, 19, 22]);
+        for (index, arrangement) in all.iter().enumerate() {
+            let nth = arrangements.nth(&BigUint::from(index)).unwrap();
+            assert_eq!(&nth, arrangement);
+        }
+        assert_eq!(arrangements.nth(&BigUint::from(8u32)), None);
+    }
+
+    #[test]
+    fn test_enumerate_broken_chain() {
+        let voltages = parse(EXAMPLE1).unwrap();
+        let arrangements = Arrangements::new(&voltages, 2);
+        assert_eq!(arrangements.iter().next(), None);
+        assert_eq!(arrangements.nth(&BigUint::ZERO), None);
+    }
 }
@@ -1,6 +1,11 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
 use std::fmt::{Display, Write};
+use std::num::ParseIntError;
 use std::ops::{Index, IndexMut};
 use std::str::FromStr;
+use std::time::Duration;
+use std::{io, thread};
 
 use thiserror::Error;
 
@@ -8,6 +13,14 @@ use thiserror::Error;
 enum ParseError {
     #[error("Invalid tile: {0:?}")]
     InvalidTile(char),
+    #[error("Unknown neighborhood: {0:?}")]
+    UnknownNeighborhood(String),
+    #[error("Line of sight must reach at least one seat")]
+    ZeroDistance,
+    #[error("Syntax error")]
+    SyntaxError,
+    #[error(transparent)]
+    InvalidNumber(#[from] ParseIntError),
 }
 
 #[derive(Debug, Clone)]
@@ -18,21 +31,6 @@ struct Grid<T> {
     height: usize,
 }
 
-impl<T> Grid<T> {
-    fn new_default(width: usize, height: usize) -> Self
-    where
-        T: Default,
-    {
-        let data = (0..width * height).map(|_| T::default()).collect();
-        Self {
-            data,
-            stride: width,
-            width,
-            height,
-        }
-    }
-}
-
 impl<T> FromStr for Grid<T>
 where
     T: TryFrom<u8> + Default,
@@ -132,123 +130,441 @@ impl Display for Tile {
     }
 }
 
+// Every seat's visible neighbors, as compact index arrays over the seats only.
+// Floor never changes, so it is left out entirely. The neighbors of seat `i`
+// are `neighbors[offsets[i]..offsets[i + 1]]`.
 #[derive(Debug, Clone)]
-struct Simulation {
-    state: Grid<Tile>,
-    counts: Grid<u8>,
+struct NeighborTable {
+    seats: Vec<usize>,
+    offsets: Vec<u32>,
+    neighbors: Vec<u32>,
 }
 
-impl Simulation {
-    fn new(grid: &Grid<Tile>) -> Self {
-        let state = grid.clone();
-        let counts = Grid::new_default(state.width, state.height);
-        Self { state, counts }
+impl NeighborTable {
+    fn new(grid: &Grid<Tile>, neighborhood: Neighborhood) -> Self {
+        let &Grid {
+            stride,
+            width,
+            height,
+            ..
+        } = grid;
+        let mut seat_ids = vec![u32::MAX; grid.data.len()];
+        let mut seats = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                if grid[(y, x)] != Tile::Floor {
+                    seat_ids[y * stride + x] = u32::try_from(seats.len()).unwrap();
+                    seats.push(y * stride + x);
+                }
+            }
+        }
+        let edges = match neighborhood {
+            Neighborhood::Adjacent => Self::sight_edges(grid, &seat_ids, 1),
+            Neighborhood::LineOfSight { max_distance } => {
+                Self::sight_edges(grid, &seat_ids, max_distance)
+            }
+            Neighborhood::Knight => Self::knight_edges(grid, &seat_ids),
+        };
+        let mut offsets = vec![0; seats.len() + 1];
+        for &(from, _) in &edges {
+            offsets[from as usize + 1] += 1;
+        }
+        for ix in 1..offsets.len() {
+            offsets[ix] += offsets[ix - 1];
+        }
+        let mut fill = offsets.clone();
+        let mut neighbors = vec![0; edges.len()];
+        for (from, to) in edges {
+            neighbors[fill[from as usize] as usize] = to;
+            fill[from as usize] += 1;
+        }
+        Self {
+            seats,
+            offsets,
+            neighbors,
+        }
     }
 
-    fn tick(&mut self, strategy: Strategy) -> bool {
-        let max_distance = match strategy {
-            Strategy::NearIntolerant => 1,
-            Strategy::FarTolerant => isize::MAX,
-        };
+    // Seeing is symmetric, so it is enough to look in the four directions that
+    // point back in scan order, and add the edge both ways. The nearest seat in
+    // a direction is either the next cell, or the nearest seat seen from that
+    // cell, which has already been visited.
+    fn sight_edges(grid: &Grid<Tile>, seat_ids: &[u32], max_distance: usize) -> Vec<(u32, u32)> {
         let &Grid {
             stride,
             width,
             height,
             ..
-        } = &self.counts;
-        for (y, counts_row) in self.counts.data.chunks_mut(stride).enumerate() {
-            for (x, counts) in counts_row[..width].iter_mut().enumerate() {
-                *counts = 0;
-                for (dx, dy) in [
-                    (-1, -1),
-                    (-1, 0),
-                    (-1, 1),
-                    (0, 1),
-                    (1, 1),
-                    (1, 0),
-                    (1, -1),
-                    (0, -1),
-                ] {
-                    for distance in 1..=max_distance {
-                        if let Some(x1) = x.checked_add_signed(distance * dx)
-                            && x1 < width
-                            && let Some(y1) = y.checked_add_signed(distance * dy)
-                            && y1 < height
-                        {
-                            match self.state[(y1, x1)] {
-                                Tile::Floor => (),
-                                Tile::Empty => break,
-                                Tile::Occupied => {
-                                    *counts += 1;
-                                    break;
-                                }
-                            }
-                        } else {
-                            break;
-                        }
+        } = grid;
+        let mut edges = Vec::new();
+        for (dy, dx) in [(-1, -1), (-1, 0), (-1, 1), (0, -1)] {
+            let mut nearest = vec![None::<(u32, usize)>; grid.data.len()];
+            for y in 0..height {
+                for x in 0..width {
+                    let Some(y1) = y.checked_add_signed(dy) else {
+                        continue;
+                    };
+                    let Some(x1) = x.checked_add_signed(dx).filter(|&x1| x1 < width) else {
+                        continue;
+                    };
+                    let next = y1 * stride + x1;
+                    let found = if seat_ids[next] == u32::MAX {
+                        nearest[next].filter(|&(_, distance)| distance < max_distance)
+                    } else {
+                        Some((seat_ids[next], 0))
+                    };
+                    let here = y * stride + x;
+                    nearest[here] = found.map(|(seat, distance)| (seat, distance + 1));
+                    if let Some((seat, _)) = found
+                        && seat_ids[here] != u32::MAX
+                    {
+                        edges.push((seat_ids[here], seat));
+                        edges.push((seat, seat_ids[here]));
                     }
                 }
             }
         }
-        let mut any_change = false;
-        for (counts_row, tiles_row) in self
-            .counts
-            .data
-            .chunks(self.counts.stride)
-            .zip(self.state.data.chunks_mut(self.state.stride))
-        {
-            for (&counts, tile) in counts_row[..width].iter().zip(&mut tiles_row[..width]) {
-                let new_tile = match (*tile, counts, strategy) {
-                    (Tile::Empty, 0, _) => Tile::Occupied,
-                    (Tile::Occupied, 4, Strategy::NearIntolerant) | (Tile::Occupied, 5..=8, _) => {
-                        Tile::Empty
+        edges
+    }
+
+    fn knight_edges(grid: &Grid<Tile>, seat_ids: &[u32]) -> Vec<(u32, u32)> {
+        let &Grid {
+            stride,
+            width,
+            height,
+            ..
+        } = grid;
+        let mut edges = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let here = seat_ids[y * stride + x];
+                if here == u32::MAX {
+                    continue;
+                }
+                for (dy, dx) in [(-1, -2), (-1, 2), (-2, -1), (-2, 1)] {
+                    if let Some(y1) = y.checked_add_signed(dy)
+                        && let Some(x1) = x.checked_add_signed(dx)
+                        && x1 < width
+                        && seat_ids[y1 * stride + x1] != u32::MAX
+                    {
+                        let seat = seat_ids[y1 * stride + x1];
+                        edges.push((here, seat));
+                        edges.push((seat, here));
                     }
-                    (old, _, _) => old,
-                };
-                any_change = any_change || new_tile != *tile;
-                *tile = new_tile;
+                }
             }
         }
-        any_change
+        edges
+    }
+
+    fn neighbors(&self, seat: u32) -> &[u32] {
+        let start = self.offsets[seat as usize] as usize;
+        let end = self.offsets[seat as usize + 1] as usize;
+        &self.neighbors[start..end]
+    }
+}
+
+// Keeps a running count of occupied neighbors per seat, and only re-evaluates
+// the seats that changed, or had a neighbor change, on the previous tick.
+// `layout_hash` is the XOR of `seat_key` over the occupied seats, so flipping a
+// seat updates it without looking at the rest of the layout.
+#[derive(Debug, Clone)]
+struct Simulation {
+    state: Grid<Tile>,
+    rules: Rules,
+    table: NeighborTable,
+    occupied_neighbors: Vec<u8>,
+    pending: Vec<u32>,
+    is_pending: Vec<bool>,
+    layout_hash: u64,
+    changed: Vec<u32>,
+}
+
+// A fixed pseudo-random key per seat (splitmix64), for Zobrist hashing.
+const fn seat_key(seat: u32) -> u64 {
+    let mut z = (seat as u64)
+        .wrapping_add(1)
+        .wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// Whether flipping every seat in `toggles` leaves the layout unchanged, i.e.
+// every seat appears an even number of times. `parity` must be all false, and
+// is left that way.
+fn cancels_out(toggles: &[u32], parity: &mut [bool]) -> bool {
+    for &seat in toggles {
+        parity[seat as usize] ^= true;
+    }
+    let cancels = toggles.iter().all(|&seat| !parity[seat as usize]);
+    for &seat in toggles {
+        parity[seat as usize] = false;
+    }
+    cancels
+}
+
+impl Simulation {
+    fn new(grid: &Grid<Tile>, rules: Rules) -> Self {
+        let table = NeighborTable::new(grid, rules.neighborhood);
+        let seat_count = u32::try_from(table.seats.len()).unwrap();
+        let mut occupied_neighbors = vec![0; table.seats.len()];
+        let mut layout_hash = 0;
+        for seat in 0..seat_count {
+            if grid.data[table.seats[seat as usize]] == Tile::Occupied {
+                layout_hash ^= seat_key(seat);
+                for &neighbor in table.neighbors(seat) {
+                    occupied_neighbors[neighbor as usize] += 1;
+                }
+            }
+        }
+        Self {
+            state: grid.clone(),
+            rules,
+            occupied_neighbors,
+            pending: (0..seat_count).collect(),
+            is_pending: vec![true; table.seats.len()],
+            table,
+            layout_hash,
+            changed: Vec::new(),
+        }
+    }
+
+    fn tick(&mut self) -> bool {
+        let mut changed = Vec::new();
+        for seat in std::mem::take(&mut self.pending) {
+            self.is_pending[seat as usize] = false;
+            let tile = self.state.data[self.table.seats[seat as usize]];
+            let count = self.occupied_neighbors[seat as usize];
+            let new_tile = match tile {
+                Tile::Empty if count <= self.rules.occupy => Tile::Occupied,
+                Tile::Occupied if count >= self.rules.vacate => Tile::Empty,
+                old => old,
+            };
+            if new_tile != tile {
+                changed.push((seat, new_tile));
+            }
+        }
+        self.changed.clear();
+        for &(seat, new_tile) in &changed {
+            self.state.data[self.table.seats[seat as usize]] = new_tile;
+            self.layout_hash ^= seat_key(seat);
+            self.changed.push(seat);
+            for &neighbor in std::iter::once(&seat).chain(self.table.neighbors(seat)) {
+                if !self.is_pending[neighbor as usize] {
+                    self.is_pending[neighbor as usize] = true;
+                    self.pending.push(neighbor);
+                }
+                if neighbor == seat {
+                    continue;
+                }
+                let count = &mut self.occupied_neighbors[neighbor as usize];
+                if new_tile == Tile::Occupied {
+                    *count += 1;
+                } else {
+                    *count -= 1;
+                }
+            }
+        }
+        !changed.is_empty()
+    }
+
+    fn occupied(&self) -> usize {
+        self.state
+            .data
+            .iter()
+            .filter(|tile| matches!(tile, Tile::Occupied))
+            .count()
+    }
+
+    // Ticks until a state repeats, calling `visit` with every distinct state on
+    // the way, starting with the initial one. A layout that settles down is a
+    // cycle with period 1.
+    //
+    // States are looked up by layout hash. On a hash hit, the earlier state is
+    // only the same if the seats flipped since then cancel out, which is
+    // checked against the log of changes rather than a snapshot of every seat.
+    fn run<E>(
+        &mut self,
+        mut visit: impl FnMut(usize, &Grid<Tile>) -> Result<(), E>,
+    ) -> Result<Cycle, E> {
+        let mut seen: HashMap<u64, Vec<usize>> = HashMap::new();
+        let mut toggles = Vec::new();
+        let mut tick_starts = Vec::new();
+        let mut parity = vec![false; self.table.seats.len()];
+        let mut tick = 0;
+        loop {
+            tick_starts.push(toggles.len());
+            let earlier = seen.entry(self.layout_hash).or_default();
+            if let Some(&first) = earlier
+                .iter()
+                .find(|&&earlier| cancels_out(&toggles[tick_starts[earlier]..], &mut parity))
+            {
+                return Ok(Cycle {
+                    first_repeat: tick,
+                    period: tick - first,
+                });
+            }
+            earlier.push(tick);
+            visit(tick, &self.state)?;
+            tick += 1;
+            if !self.tick() {
+                return Ok(Cycle {
+                    first_repeat: tick,
+                    period: 1,
+                });
+            }
+            toggles.extend_from_slice(&self.changed);
+        }
+    }
+
+    fn find_cycle(&mut self) -> Cycle {
+        let Ok(cycle) = self.run(|_, _| Ok::<_, Infallible>(()));
+        cycle
+    }
+
+    #[allow(unused)]
+    fn animate(&mut self, out: &mut impl io::Write, style: FrameStyle) -> io::Result<Cycle> {
+        self.run(|tick, state| {
+            match style {
+                FrameStyle::Text => writeln!(out, "Tick {tick}:\n{state}\n")?,
+                FrameStyle::Terminal { delay } => {
+                    write!(out, "\x1b[2J\x1b[HTick {tick}:\n{state}\n")?;
+                    out.flush()?;
+                    thread::sleep(delay);
+                }
+            }
+            Ok(())
+        })
     }
 }
 
+// The state after `first_repeat` ticks is the same as the one `period` ticks
+// earlier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cycle {
+    first_repeat: usize,
+    period: usize,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameStyle {
+    Text,
+    Terminal { delay: Duration },
+}
+
 #[aoc_generator(day11)]
 fn parse(input: &str) -> Result<Grid<Tile>, ParseError> {
     input.parse()
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Strategy {
-    NearIntolerant,
-    FarTolerant,
+enum Neighborhood {
+    Adjacent,
+    LineOfSight { max_distance: usize },
+    Knight,
+}
+
+impl FromStr for Neighborhood {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.split_once(':') {
+            None if s == "adjacent" => Self::Adjacent,
+            None if s == "sight" => Self::LineOfSight {
+                max_distance: usize::MAX,
+            },
+            None if s == "knight" => Self::Knight,
+            Some(("sight", max_distance)) => match max_distance.parse()? {
+                0 => return Err(ParseError::ZeroDistance),
+                max_distance => Self::LineOfSight { max_distance },
+            },
+            _ => return Err(ParseError::UnknownNeighborhood(s.to_string())),
+        })
+    }
+}
+
+impl Display for Neighborhood {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Adjacent => f.write_str("adjacent"),
+            Self::LineOfSight {
+                max_distance: usize::MAX,
+            } => f.write_str("sight"),
+            Self::LineOfSight { max_distance } => write!(f, "sight:{max_distance}"),
+            Self::Knight => f.write_str("knight"),
+        }
+    }
+}
+
+// An empty seat is taken when at most `occupy` of its neighbors are occupied,
+// and an occupied seat is left when at least `vacate` of them are. Written as
+// `neighborhood/occupy/vacate`, for example `adjacent/0/4` or `sight:3/0/5`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rules {
+    neighborhood: Neighborhood,
+    occupy: u8,
+    vacate: u8,
+}
+
+impl Rules {
+    const NEAR_INTOLERANT: Self = Self {
+        neighborhood: Neighborhood::Adjacent,
+        occupy: 0,
+        vacate: 4,
+    };
+    const FAR_TOLERANT: Self = Self {
+        neighborhood: Neighborhood::LineOfSight {
+            max_distance: usize::MAX,
+        },
+        occupy: 0,
+        vacate: 5,
+    };
+}
+
+impl FromStr for Rules {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('/');
+        let (Some(neighborhood), Some(occupy), Some(vacate), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(ParseError::SyntaxError);
+        };
+        Ok(Self {
+            neighborhood: neighborhood.parse()?,
+            occupy: occupy.parse()?,
+            vacate: vacate.parse()?,
+        })
+    }
+}
+
+impl Display for Rules {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}/{}", self.neighborhood, self.occupy, self.vacate)
+    }
 }
 
 #[aoc(day11, part1)]
 fn part_1(seat_layout: &Grid<Tile>) -> usize {
-    let mut sim = Simulation::new(seat_layout);
-    while sim.tick(Strategy::NearIntolerant) {}
-    sim.state
-        .data
-        .iter()
-        .filter(|tile| matches!(tile, Tile::Occupied))
-        .count()
+    let mut sim = Simulation::new(seat_layout, Rules::NEAR_INTOLERANT);
+    sim.find_cycle();
+    sim.occupied()
 }
 
 #[aoc(day11, part2)]
 fn part_2(seat_layout: &Grid<Tile>) -> usize {
-    let mut sim = Simulation::new(seat_layout);
-    while sim.tick(Strategy::FarTolerant) {}
-    sim.state
-        .data
-        .iter()
-        .filter(|tile| matches!(tile, Tile::Occupied))
-        .count()
+    let mut sim = Simulation::new(seat_layout, Rules::FAR_TOLERANT);
+    sim.find_cycle();
+    sim.occupied()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use test_case::test_case;
 
     const EXAMPLE: &str = "\
         L.LL.LL.LL\n\
@@ -276,4 +592,219 @@ mod tests {
         let result = part_2(&seat_layout);
         assert_eq!(result, 26);
     }
+
+    #[test_case("adjacent/0/4" => Rules::NEAR_INTOLERANT)]
+    #[test_case("sight/0/5" => Rules::FAR_TOLERANT)]
+    #[test_case("sight:3/1/6" => Rules {
+        neighborhood: Neighborhood::LineOfSight { max_distance: 3 },
+        occupy: 1,
+        vacate: 6,
+    })]
+    #[test_case("knight/0/3" => Rules {
+        neighborhood: Neighborhood::Knight,
+        occupy: 0,
+        vacate: 3,
+    })]
+    fn test_parse_rules(input: &str) -> Rules {
+        let rules = input.parse::<Rules>().unwrap();
+        assert_eq!(rules.to_string(), input);
+        rules
+    }
+
+    #[test_case("adjacent/0")]
+    #[test_case("adjacent/0/4/5")]
+    #[test_case("diagonal/0/4")]
+    #[test_case("sight:far/0/4")]
+    #[test_case("sight:0/0/4")]
+    #[test_case("adjacent/-1/4")]
+    fn test_parse_rules_invalid(input: &str) {
+        assert!(input.parse::<Rules>().is_err());
+    }
+
+    #[test_case("adjacent/0/4" => 37)]
+    #[test_case("sight:1/0/4" => 37)]
+    #[test_case("sight/0/5" => 26)]
+    fn test_parsed_rules(rules: &str) -> usize {
+        let seat_layout = parse(EXAMPLE).unwrap();
+        let mut sim = Simulation::new(&seat_layout, rules.parse().unwrap());
+        while sim.tick() {}
+        sim.occupied()
+    }
+
+    #[test_case(Rules::NEAR_INTOLERANT => Cycle { first_repeat: 6, period: 1 })]
+    #[test_case(Rules::FAR_TOLERANT => Cycle { first_repeat: 7, period: 1 })]
+    fn test_find_cycle_stable(rules: Rules) -> Cycle {
+        let seat_layout = parse(EXAMPLE).unwrap();
+        let mut sim = Simulation::new(&seat_layout, rules);
+        sim.find_cycle()
+    }
+
+    #[test]
+    fn test_find_cycle_oscillating() {
+        let seat_layout = parse("L.L\nLLL").unwrap();
+        let mut sim = Simulation::new(&seat_layout, "adjacent/0/1".parse().unwrap());
+        let result = sim.find_cycle();
+        assert_eq!(
+            result,
+            Cycle {
+                first_repeat: 2,
+                period: 2
+            }
+        );
+    }
+
+    #[test_case(&[] => true)]
+    #[test_case(&[1, 2, 2, 1] => true)]
+    #[test_case(&[1, 2, 1] => false)]
+    #[test_case(&[0, 0, 0] => false)]
+    fn test_cancels_out(toggles: &[u32]) -> bool {
+        let mut parity = vec![false; 3];
+        let cancels = cancels_out(toggles, &mut parity);
+        assert_eq!(parity, [false; 3]);
+        cancels
+    }
+
+    #[test]
+    fn test_layout_hash_follows_ticks() {
+        let seat_layout = parse(EXAMPLE).unwrap();
+        let mut sim = Simulation::new(&seat_layout, Rules::NEAR_INTOLERANT);
+        while sim.tick() {
+            let fresh = Simulation::new(&sim.state, Rules::NEAR_INTOLERANT);
+            assert_eq!(sim.layout_hash, fresh.layout_hash);
+        }
+    }
+
+    #[test]
+    fn test_animate_text() {
+        let seat_layout = parse("LL.\nL.L").unwrap();
+        let mut sim = Simulation::new(&seat_layout, Rules::NEAR_INTOLERANT);
+        let mut out = Vec::new();
+        let result = sim.animate(&mut out, FrameStyle::Text).unwrap();
+        assert_eq!(
+            result,
+            Cycle {
+                first_repeat: 2,
+                period: 1
+            }
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Tick 0:\nLL \nL L\n\nTick 1:\n## \n# #\n\n"
+        );
+    }
+
+    #[test]
+    fn test_animate_terminal() {
+        let seat_layout = parse("LL").unwrap();
+        let mut sim = Simulation::new(&seat_layout, "adjacent/0/1".parse().unwrap());
+        let mut out = Vec::new();
+        let style = FrameStyle::Terminal {
+            delay: Duration::ZERO,
+        };
+        sim.animate(&mut out, style).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b[2J\x1b[HTick 0:\nLL\n\x1b[2J\x1b[HTick 1:\n##\n"
+        );
+    }
+
+    #[test]
+    fn test_knight_neighborhood() {
+        let grid = parse(
+            "\
+            ##L##\n\
+            #...#\n\
+            ..L..\n\
+            #...#\n\
+            ..#..\
+            ",
+        )
+        .unwrap();
+        let table = NeighborTable::new(&grid, Neighborhood::Knight);
+        let seat = table
+            .seats
+            .iter()
+            .position(|&ix| ix == 2 * grid.stride + 2)
+            .unwrap();
+        let mut neighbors = table
+            .neighbors(u32::try_from(seat).unwrap())
+            .iter()
+            .map(|&neighbor| table.seats[neighbor as usize])
+            .collect::<Vec<_>>();
+        neighbors.sort_unstable();
+        assert_eq!(neighbors, [1, 3, 5, 9, 15, 19]);
+    }
+
+    fn visible_occupied(grid: &Grid<Tile>, max_distance: usize, pos: (usize, usize)) -> usize {
+        let table = NeighborTable::new(grid, Neighborhood::LineOfSight { max_distance });
+        let seat = table
+            .seats
+            .iter()
+            .position(|&ix| ix == pos.0 * grid.stride + pos.1)
+            .unwrap();
+        table
+            .neighbors(u32::try_from(seat).unwrap())
+            .iter()
+            .filter(|&&neighbor| grid.data[table.seats[neighbor as usize]] == Tile::Occupied)
+            .count()
+    }
+
+    #[test]
+    fn test_neighbor_table_sees_eight() {
+        let grid = parse(
+            "\
+            .......#.\n\
+            ...#.....\n\
+            .#.......\n\
+            .........\n\
+            ..#L....#\n\
+            ....#....\n\
+            .........\n\
+            #........\n\
+            ...#.....\
+            ",
+        )
+        .unwrap();
+        assert_eq!(visible_occupied(&grid, usize::MAX, (4, 3)), 8);
+        assert_eq!(visible_occupied(&grid, 3, (4, 3)), 5);
+        assert_eq!(visible_occupied(&grid, 1, (4, 3)), 2);
+    }
+
+    #[test]
+    fn test_neighbor_table_blocked_by_empty_seat() {
+        let grid = parse(
+            "\
+            .............\n\
+            .L.L.#.#.#.#.\n\
+            .............\
+            ",
+        )
+        .unwrap();
+        let table = NeighborTable::new(
+            &grid,
+            Neighborhood::LineOfSight {
+                max_distance: usize::MAX,
+            },
+        );
+        assert_eq!(table.seats.len(), 6);
+        assert_eq!(table.neighbors(0), [1]);
+        assert_eq!(visible_occupied(&grid, usize::MAX, (1, 1)), 0);
+    }
+
+    #[test]
+    fn test_neighbor_table_sees_none() {
+        let grid = parse(
+            "\
+            .##.##.\n\
+            #.#.#.#\n\
+            ##...##\n\
+            ...L...\n\
+            ##...##\n\
+            #.#.#.#\n\
+            .##.##.\
+            ",
+        )
+        .unwrap();
+        assert_eq!(visible_occupied(&grid, usize::MAX, (3, 3)), 0);
+    }
 }
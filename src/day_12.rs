@@ -1,3 +1,4 @@
+use std::fmt::Write;
 use std::num::ParseIntError;
 use std::str::FromStr;
 
@@ -151,37 +152,315 @@ fn parse(input: &str) -> Result<Vec<Instruction>, ParseError> {
     input.lines().map(str::parse).collect()
 }
 
-#[aoc(day12, part1)]
-fn part_1(instructions: &[Instruction]) -> u64 {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WaypointStep<P> {
+    ship: P,
+    waypoint: P,
+}
+
+fn ship_route(instructions: &[Instruction]) -> Vec<Position> {
     let mut ship = Position::default();
     let mut facing = Cardinal::East;
+    let mut route = vec![ship];
     for &instr in instructions {
         match instr {
             Instruction::Cardinal(dir, dist) => ship.move_cardinal(dir, dist),
             Instruction::Rotation(rot, deg) => facing.rotate(rot, deg),
             Instruction::Forward(dist) => ship.move_cardinal(facing, dist),
         }
+        route.push(ship);
     }
-    ship.dist()
+    route
 }
 
-#[aoc(day12, part2)]
-fn part_2(instructions: &[Instruction]) -> u64 {
+fn waypoint_route(instructions: &[Instruction]) -> Vec<WaypointStep<Position>> {
     let mut ship = Position::default();
     let mut waypoint: Position = Position::new(1, 10);
+    let mut route = vec![WaypointStep { ship, waypoint }];
     for &instr in instructions {
         match instr {
             Instruction::Cardinal(dir, dist) => waypoint.move_cardinal(dir, dist),
             Instruction::Rotation(rot, deg) => waypoint.rotate(rot, deg),
             Instruction::Forward(times) => ship.move_by_waypoint(waypoint, times),
         }
+        route.push(WaypointStep { ship, waypoint });
+    }
+    route
+}
+
+#[aoc(day12, part1)]
+fn part_1(instructions: &[Instruction]) -> u64 {
+    let route = ship_route(instructions);
+    route.last().copied().unwrap_or_default().dist()
+}
+
+#[aoc(day12, part2)]
+fn part_2(instructions: &[Instruction]) -> u64 {
+    let route = waypoint_route(instructions);
+    route.last().map_or(0, |step| step.ship.dist())
+}
+
+// Navigation that allows turning by any whole number of degrees. Turns are
+// counterclockwise, so `L` is positive and `R` is negative.
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FreeInstruction {
+    Cardinal(Cardinal, u32),
+    Turn(i32),
+    Forward(u32),
+}
+
+impl FromStr for FreeInstruction {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match *s.as_bytes() {
+            [b'N', ..] => Self::Cardinal(Cardinal::North, s[1..].parse()?),
+            [b'E', ..] => Self::Cardinal(Cardinal::East, s[1..].parse()?),
+            [b'S', ..] => Self::Cardinal(Cardinal::South, s[1..].parse()?),
+            [b'W', ..] => Self::Cardinal(Cardinal::West, s[1..].parse()?),
+            [b'L', ..] => Self::turn(s[1..].parse()?),
+            [b'R', ..] => Self::turn(-s[1..].parse::<i64>()?.rem_euclid(360)),
+            [b'F', ..] => Self::Forward(s[1..].parse()?),
+            _ => return Err(ParseError::SyntaxError),
+        })
+    }
+}
+
+impl FreeInstruction {
+    // Turns are reduced to a single revolution, so any amount fits.
+    fn turn(degrees: i64) -> Self {
+        Self::Turn(i32::try_from(degrees.rem_euclid(360)).unwrap())
+    }
+}
+
+#[allow(unused)]
+fn parse_free(input: &str) -> Result<Vec<FreeInstruction>, ParseError> {
+    input.lines().map(str::parse).collect()
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct FreePosition {
+    north: f64,
+    east: f64,
+}
+
+#[allow(unused)]
+impl FreePosition {
+    const fn new(north: f64, east: f64) -> Self {
+        Self { north, east }
+    }
+
+    // Quarter turns are kept exact, so routes that only use them stay on whole
+    // numbers.
+    fn unit(degrees: i32) -> Self {
+        match degrees.rem_euclid(360) {
+            0 => Self::new(0.0, 1.0),
+            90 => Self::new(1.0, 0.0),
+            180 => Self::new(0.0, -1.0),
+            270 => Self::new(-1.0, 0.0),
+            deg => {
+                let (sin, cos) = f64::from(deg).to_radians().sin_cos();
+                Self::new(sin, cos)
+            }
+        }
+    }
+
+    fn move_cardinal(&mut self, direction: Cardinal, dist: u32) {
+        match direction {
+            Cardinal::North => self.north += f64::from(dist),
+            Cardinal::East => self.east += f64::from(dist),
+            Cardinal::South => self.north -= f64::from(dist),
+            Cardinal::West => self.east -= f64::from(dist),
+        }
+    }
+
+    fn move_by(&mut self, direction: Self, times: u32) {
+        self.east += direction.east * f64::from(times);
+        self.north += direction.north * f64::from(times);
+    }
+
+    fn rotate(&mut self, degrees: i32) {
+        let Self {
+            north: sin,
+            east: cos,
+        } = Self::unit(degrees);
+        (self.east, self.north) = (
+            self.east.mul_add(cos, -self.north * sin),
+            self.east.mul_add(sin, self.north * cos),
+        );
+    }
+
+    const fn dist(self) -> f64 {
+        self.east.abs() + self.north.abs()
+    }
+}
+
+impl From<Position> for FreePosition {
+    fn from(value: Position) -> Self {
+        // Routes are far too short for the conversion to lose precision
+        #[expect(clippy::cast_precision_loss)]
+        Self::new(value.north as f64, value.east as f64)
     }
-    ship.dist()
+}
+
+#[allow(unused)]
+fn free_ship_route(instructions: &[FreeInstruction]) -> Vec<FreePosition> {
+    let mut ship = FreePosition::default();
+    let mut heading = 0;
+    let mut route = vec![ship];
+    for &instr in instructions {
+        match instr {
+            FreeInstruction::Cardinal(dir, dist) => ship.move_cardinal(dir, dist),
+            FreeInstruction::Turn(deg) => heading = (heading + deg.rem_euclid(360)).rem_euclid(360),
+            FreeInstruction::Forward(dist) => ship.move_by(FreePosition::unit(heading), dist),
+        }
+        route.push(ship);
+    }
+    route
+}
+
+#[allow(unused)]
+fn free_waypoint_route(instructions: &[FreeInstruction]) -> Vec<WaypointStep<FreePosition>> {
+    let mut ship = FreePosition::default();
+    let mut waypoint = FreePosition::new(1.0, 10.0);
+    let mut route = vec![WaypointStep { ship, waypoint }];
+    for &instr in instructions {
+        match instr {
+            FreeInstruction::Cardinal(dir, dist) => waypoint.move_cardinal(dir, dist),
+            FreeInstruction::Turn(deg) => waypoint.rotate(deg),
+            FreeInstruction::Forward(times) => ship.move_by(waypoint, times),
+        }
+        route.push(WaypointStep { ship, waypoint });
+    }
+    route
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShipModel {
+    Heading,
+    Waypoint,
+}
+
+// Longest side of the drawing, not counting the margins
+const SVG_SIZE: f64 = 760.0;
+const SVG_MARGIN: f64 = 20.0;
+const SVG_CAPTION: f64 = 30.0;
+
+// Replays the instructions with either ship model, and draws the route of the
+// ship, the waypoint after every step, and the final Manhattan distance. The
+// drawing is scaled so the longest side of the bounding box fills the image.
+#[allow(unused)]
+fn render_svg(instructions: &[Instruction], model: ShipModel) -> String {
+    let (ships, waypoints, distance) = match model {
+        ShipModel::Heading => {
+            let route = ship_route(instructions);
+            let distance = route.last().copied().unwrap_or_default().dist();
+            (route, Vec::new(), distance)
+        }
+        ShipModel::Waypoint => {
+            let route = waypoint_route(instructions);
+            let distance = route.last().map_or(0, |step| step.ship.dist());
+            let ships = route.iter().map(|step| step.ship).collect();
+            let waypoints = route
+                .iter()
+                .map(|step| {
+                    Position::new(
+                        step.ship.north + step.waypoint.north,
+                        step.ship.east + step.waypoint.east,
+                    )
+                })
+                .collect();
+            (ships, waypoints, distance)
+        }
+    };
+    let ships = ships
+        .into_iter()
+        .map(FreePosition::from)
+        .collect::<Vec<_>>();
+    let waypoints = waypoints
+        .into_iter()
+        .map(FreePosition::from)
+        .collect::<Vec<_>>();
+
+    let all = || ships.iter().chain(&waypoints);
+    let min_east = all().map(|pos| pos.east).fold(f64::INFINITY, f64::min);
+    let max_east = all().map(|pos| pos.east).fold(f64::NEG_INFINITY, f64::max);
+    let min_north = all().map(|pos| pos.north).fold(f64::INFINITY, f64::min);
+    let max_north = all().map(|pos| pos.north).fold(f64::NEG_INFINITY, f64::max);
+    let scale = SVG_SIZE / (max_east - min_east).max(max_north - min_north).max(1.0);
+    let x = |pos: &FreePosition| (pos.east - min_east).mul_add(scale, SVG_MARGIN);
+    let y = |pos: &FreePosition| (max_north - pos.north).mul_add(scale, SVG_MARGIN);
+    let width = (max_east - min_east).mul_add(scale, 2.0 * SVG_MARGIN);
+    let height = (max_north - min_north).mul_add(scale, 2.0 * SVG_MARGIN) + SVG_CAPTION;
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width:.1}" height="{height:.1}" viewBox="0 0 {width:.1} {height:.1}">"#
+    )
+    .unwrap();
+    writeln!(svg, r#"  <rect width="100%" height="100%" fill="white"/>"#).unwrap();
+    for (ship, waypoint) in ships.iter().zip(&waypoints) {
+        writeln!(
+            svg,
+            r#"  <line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="orange" stroke-opacity="0.4"/>"#,
+            x(ship),
+            y(ship),
+            x(waypoint),
+            y(waypoint)
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r#"  <circle cx="{:.1}" cy="{:.1}" r="3" fill="orange"/>"#,
+            x(waypoint),
+            y(waypoint)
+        )
+        .unwrap();
+    }
+    let points = ships
+        .iter()
+        .map(|pos| format!("{:.1},{:.1}", x(pos), y(pos)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    writeln!(
+        svg,
+        r#"  <polyline points="{points}" fill="none" stroke="navy" stroke-width="2"/>"#
+    )
+    .unwrap();
+    if let (Some(start), Some(end)) = (ships.first(), ships.last()) {
+        writeln!(
+            svg,
+            r#"  <circle cx="{:.1}" cy="{:.1}" r="5" fill="green"/>"#,
+            x(start),
+            y(start)
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r#"  <circle cx="{:.1}" cy="{:.1}" r="5" fill="red"/>"#,
+            x(end),
+            y(end)
+        )
+        .unwrap();
+    }
+    writeln!(
+        svg,
+        r#"  <text x="{SVG_MARGIN:.1}" y="{:.1}" font-family="sans-serif" font-size="16">Manhattan distance: {distance}</text>"#,
+        height - SVG_MARGIN / 2.0
+    )
+    .unwrap();
+    svg.push_str("</svg>\n");
+    svg
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use test_case::test_case;
 
     const EXAMPLE: &str = "\
         F10\n\
@@ -219,4 +498,152 @@ mod tests {
         let result = part_2(&instructions);
         assert_eq!(result, 286);
     }
+
+    #[test]
+    fn test_ship_route() {
+        let instructions = parse(EXAMPLE).unwrap();
+        let result = ship_route(&instructions);
+        assert_eq!(
+            result,
+            [
+                Position::new(0, 0),
+                Position::new(0, 10),
+                Position::new(3, 10),
+                Position::new(3, 17),
+                Position::new(3, 17),
+                Position::new(-8, 17),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_waypoint_route() {
+        let instructions = parse(EXAMPLE).unwrap();
+        let result = waypoint_route(&instructions);
+        let ships = result.iter().map(|step| step.ship).collect::<Vec<_>>();
+        let waypoints = result.iter().map(|step| step.waypoint).collect::<Vec<_>>();
+        assert_eq!(
+            ships,
+            [
+                Position::new(0, 0),
+                Position::new(10, 100),
+                Position::new(10, 100),
+                Position::new(38, 170),
+                Position::new(38, 170),
+                Position::new(-72, 214),
+            ]
+        );
+        assert_eq!(
+            waypoints,
+            [
+                Position::new(1, 10),
+                Position::new(1, 10),
+                Position::new(4, 10),
+                Position::new(4, 10),
+                Position::new(-10, 4),
+                Position::new(-10, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_free_routes_match_quarter_turns() {
+        let instructions = parse(EXAMPLE).unwrap();
+        let free_instructions = parse_free(EXAMPLE).unwrap();
+        let expected = ship_route(&instructions)
+            .into_iter()
+            .map(FreePosition::from)
+            .collect::<Vec<_>>();
+        assert_eq!(free_ship_route(&free_instructions), expected);
+        let expected = waypoint_route(&instructions)
+            .into_iter()
+            .map(|step| WaypointStep {
+                ship: step.ship.into(),
+                waypoint: step.waypoint.into(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(free_waypoint_route(&free_instructions), expected);
+    }
+
+    #[test]
+    fn test_render_svg_scaling() {
+        let instructions = parse("F10\nN10").unwrap();
+        let svg = render_svg(&instructions, ShipModel::Heading);
+        assert!(svg.starts_with(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="800.0" height="830.0" viewBox="0 0 800.0 830.0">"#
+        ));
+        assert!(svg.contains(r#"<polyline points="20.0,780.0 780.0,780.0 780.0,20.0" "#));
+        assert!(svg.contains(">Manhattan distance: 20</text>"));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    #[test_case(ShipModel::Heading => (0, 25))]
+    #[test_case(ShipModel::Waypoint => (6, 286))]
+    fn test_render_svg(model: ShipModel) -> (usize, u64) {
+        let instructions = parse(EXAMPLE).unwrap();
+        let svg = render_svg(&instructions, model);
+        let waypoints = svg.matches(r#"fill="orange""#).count();
+        let distance = svg
+            .split_once("Manhattan distance: ")
+            .and_then(|(_, rest)| rest.split_once('<'))
+            .map(|(distance, _)| distance.parse().unwrap())
+            .unwrap();
+        assert_eq!(svg.matches("<polyline").count(), 1);
+        (waypoints, distance)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{actual} is not close to {expected}"
+        );
+    }
+
+    #[test]
+    fn test_free_ship_route() {
+        let instructions = parse_free("L45\nF10\nR135\nF10").unwrap();
+        let result = free_ship_route(&instructions);
+        let last = result.last().unwrap();
+        assert_close(last.north, 50f64.sqrt() - 10.0);
+        assert_close(last.east, 50f64.sqrt());
+        assert_close(last.dist(), 10.0);
+    }
+
+    #[test_case("L2147483647" => FreeInstruction::Turn(127))]
+    #[test_case("R2147483648" => FreeInstruction::Turn(232))]
+    #[test_case("R-9223372036854775808" => FreeInstruction::Turn(8))]
+    #[test_case("L-9223372036854775808" => FreeInstruction::Turn(352))]
+    #[test_case("R9223372036854775807" => FreeInstruction::Turn(353))]
+    #[test_case("L450" => FreeInstruction::Turn(90))]
+    fn test_parse_free_turn(input: &str) -> FreeInstruction {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn test_free_ship_route_huge_turn() {
+        let instructions = [
+            FreeInstruction::Turn(90),
+            FreeInstruction::Turn(i32::MAX),
+            FreeInstruction::Forward(1),
+        ];
+        let result = free_ship_route(&instructions);
+        let last = result.last().unwrap();
+        let (sin, cos) = 217f64.to_radians().sin_cos();
+        assert_close(last.north, sin);
+        assert_close(last.east, cos);
+    }
+
+    #[test]
+    fn test_free_waypoint_route() {
+        let instructions = parse_free("N9\nR30\nF2\nL390\nF1").unwrap();
+        let result = free_waypoint_route(&instructions);
+        let last = result.last().unwrap();
+        // The waypoint is 10 units east and 10 north, and turns a net 0 degrees
+        assert_close(last.waypoint.north, 10.0);
+        assert_close(last.waypoint.east, 10.0);
+        let rotated = 20.0 * 2f64.sqrt();
+        let (sin, cos) = 15f64.to_radians().sin_cos();
+        assert_close(last.ship.north, rotated.mul_add(sin, 10.0));
+        assert_close(last.ship.east, rotated.mul_add(cos, 10.0));
+    }
 }
@@ -1,6 +1,8 @@
 use std::num::ParseIntError;
 use std::str::FromStr;
 
+use num_bigint::{BigInt, BigUint};
+use num_integer::{ExtendedGcd, Integer};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -23,6 +25,15 @@ struct Bus {
     offset: u64,
 }
 
+impl Bus {
+    // time + offset === 0 (mod id)
+    // time === -offset (mod id)
+    // time === id - offset (mod id)
+    const fn residue(self) -> u64 {
+        (self.id - self.offset % self.id) % self.id
+    }
+}
+
 impl FromStr for Input {
     type Err = ParseError;
 
@@ -57,51 +68,141 @@ fn parse(input: &str) -> Result<Input, ParseError> {
     input.parse()
 }
 
-#[aoc(day13, part1)]
-fn part_1(input: &Input) -> u64 {
-    let (delay, id) = input
-        .schedule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Departure {
+    id: u64,
+    time: u64,
+}
+
+// The first departure of every bus at or after `time`, earliest first.
+fn next_departures(schedule: &[Bus], time: u64) -> Vec<Departure> {
+    let mut departures = schedule
         .iter()
-        .map(|bus| {
-            (
-                (bus.id - input.earliest_departure % bus.id) % bus.id,
-                bus.id,
-            )
+        .map(|bus| Departure {
+            id: bus.id,
+            time: time.next_multiple_of(bus.id),
         })
-        .min()
-        .unwrap();
-    delay * id
+        .collect::<Vec<_>>();
+    departures.sort_unstable_by_key(|departure| (departure.time, departure.id));
+    departures
+}
+
+#[aoc(day13, part1)]
+fn part_1(input: &Input) -> u64 {
+    let departures = next_departures(&input.schedule, input.earliest_departure);
+    let first = departures.first().unwrap();
+    (first.time - input.earliest_departure) * first.id
+}
+
+// Every bus departs `offset` minutes after the timestamps `first + n * period`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Alignment {
+    first: u64,
+    period: u64,
+}
+
+impl Alignment {
+    // The aligned timestamps at or after `time`, in order.
+    #[allow(unused)]
+    fn times_from(self, time: u64) -> impl Iterator<Item = u64> {
+        let start = if time <= self.first {
+            Some(self.first)
+        } else {
+            (time - self.first)
+                .div_ceil(self.period)
+                .checked_mul(self.period)
+                .and_then(|delta| delta.checked_add(self.first))
+        };
+        std::iter::successors(start, move |&time| time.checked_add(self.period))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+enum AlignError {
+    #[error("The buses never depart at the requested offsets")]
+    Incompatible,
+    #[error("The period does not fit in 64 bits")]
+    Overflow,
+}
+
+// Finds when the buses depart at their offsets. There is no such time when
+// some bus ids share a factor that the offsets disagree on.
+fn align(buses: &[Bus]) -> Result<Alignment, AlignError> {
+    buses.iter().try_fold(
+        Alignment {
+            first: 0,
+            period: 1,
+        },
+        |alignment, bus| {
+            let (first, period) =
+                chinese_remainder(alignment.first, alignment.period, bus.residue(), bus.id)?;
+            Ok(Alignment { first, period })
+        },
+    )
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BigAlignment {
+    first: BigUint,
+    period: BigUint,
+}
+
+// Same as `align`, for when the period grows past 64 bits.
+fn align_big(buses: &[Bus]) -> Result<BigAlignment, AlignError> {
+    let mut first = BigInt::ZERO;
+    let mut period = BigInt::from(1);
+    for bus in buses {
+        let value = BigInt::from(bus.residue());
+        let modulus = BigInt::from(bus.id);
+        let ExtendedGcd { gcd, x: bez1, .. } = period.extended_gcd(&modulus);
+        let diff = value - &first;
+        if !diff.is_multiple_of(&gcd) {
+            return Err(AlignError::Incompatible);
+        }
+        let reduced = modulus / &gcd;
+        let step = (diff / gcd * bez1).mod_floor(&reduced);
+        let lcm = &period * reduced;
+        first = (first + &period * step).mod_floor(&lcm);
+        period = lcm;
+    }
+    Ok(BigAlignment {
+        first: first.into_parts().1,
+        period: period.into_parts().1,
+    })
 }
 
 #[aoc(day13, part2)]
-fn part_2(input: &Input) -> u64 {
-    // t + bus.offset === 0 (mod bus.id)
-    // t === -bus.offset (mod bus.id)
-    input
-        .schedule
-        .iter()
-        .copied()
-        // time + offset === 0 (mod id)
-        // time === -offset (mod id)
-        // time === id - offset (mod id)
-        .map(|bus| ((bus.id - bus.offset % bus.id) % bus.id, bus.id))
-        .reduce(|(value1, mod1), (value2, mod2)| {
-            (chinese_remainder(value1, mod1, value2, mod2), mod1 * mod2)
-        })
-        .unwrap()
-        .0
+fn part_2(input: &Input) -> Result<BigUint, AlignError> {
+    match align(&input.schedule) {
+        Ok(alignment) => Ok(alignment.first.into()),
+        Err(AlignError::Overflow) => align_big(&input.schedule).map(|alignment| alignment.first),
+        Err(err) => Err(err),
+    }
 }
 
-fn chinese_remainder(value1: u64, mod1: u64, value2: u64, mod2: u64) -> u64 {
-    let (gcd, bez1, bez2) = extended_gcd(mod1, mod2);
-    assert_eq!(gcd, 1, "Must be coprime");
-    // Have to use i128 because of multiplication overflow, but the result is < mod1*mod2
-    let signed = i128::from(value1) * i128::from(mod2) * i128::from(bez2)
-        + i128::from(value2) * i128::from(mod1) * i128::from(bez1);
-    signed
-        .rem_euclid(i128::from(mod1) * i128::from(mod2))
-        .try_into()
-        .unwrap()
+// Combines `x === value1 (mod mod1)` and `x === value2 (mod mod2)` into
+// `x === value (mod lcm(mod1, mod2))`, returning the smallest such `value` and
+// the new modulus.
+fn chinese_remainder(
+    value1: u64,
+    mod1: u64,
+    value2: u64,
+    mod2: u64,
+) -> Result<(u64, u64), AlignError> {
+    let (gcd, bez1, _) = extended_gcd(mod1, mod2);
+    let diff = i128::from(value2) - i128::from(value1);
+    if diff % i128::from(gcd) != 0 {
+        return Err(AlignError::Incompatible);
+    }
+    let lcm = u64::try_from(u128::from(mod1 / gcd) * u128::from(mod2))
+        .map_err(|_| AlignError::Overflow)?;
+    // Reduce both factors before multiplying, so the product fits in u128
+    let reduced = i128::from(mod2 / gcd);
+    let quotient = (diff / i128::from(gcd)).rem_euclid(reduced).cast_unsigned();
+    let bez1 = i128::from(bez1).rem_euclid(reduced).cast_unsigned();
+    let step = quotient * bez1 % reduced.cast_unsigned();
+    let value = (u128::from(value1) + u128::from(mod1) * step) % u128::from(lcm);
+    Ok((value.try_into().unwrap(), lcm))
 }
 
 pub const fn extended_gcd(mut x: u64, mut y: u64) -> (u64, i64, i64) {
@@ -156,9 +257,23 @@ mod tests {
         extended_gcd(x, y)
     }
 
-    #[test_case(2, 3, 3, 5 => 8)]
-    #[test_case(8, 3*5, 2, 7 => 23)]
-    fn test_chinese_remainder(a1: u64, n1: u64, a2: u64, n2: u64) -> u64 {
+    #[test_case(2, 3, 3, 5 => Ok((8, 15)))]
+    #[test_case(8, 3*5, 2, 7 => Ok((23, 105)))]
+    #[test_case(2, 4, 4, 6 => Ok((10, 12)))]
+    #[test_case(1, 4, 2, 6 => Err(AlignError::Incompatible))]
+    #[test_case(0, 4_294_967_291, 5, 4_294_967_311 => Err(AlignError::Overflow))]
+    #[test_case(
+        9_223_372_036_854_775_782,
+        9_223_372_036_854_775_783,
+        1,
+        2
+    => Ok((18_446_744_073_709_551_565, 18_446_744_073_709_551_566)))]
+    fn test_chinese_remainder(
+        a1: u64,
+        n1: u64,
+        a2: u64,
+        n2: u64,
+    ) -> Result<(u64, u64), AlignError> {
         chinese_remainder(a1, n1, a2, n2)
     }
 
@@ -170,6 +285,71 @@ mod tests {
     #[test_case("0\n1789,37,47,1889" => 1_202_161_486)]
     fn test_part_2(input: &str) -> u64 {
         let input = parse(input).unwrap();
-        part_2(&input)
+        part_2(&input).unwrap().try_into().unwrap()
+    }
+
+    #[test_case("0\n4294967291,x,4294967279,4294967231" => "2833507143679542015147522079")]
+    #[test_case("0\n1000000007,998244353,x,x,x,1000000009,17" => "10964917997938685853289822835")]
+    fn test_part_2_exceeds_u64(input: &str) -> String {
+        let input = parse(input).unwrap();
+        part_2(&input).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_align_big_matches_align() {
+        let input = parse(EXAMPLE1).unwrap();
+        let small = align(&input.schedule).unwrap();
+        let big = align_big(&input.schedule).unwrap();
+        assert_eq!(big.first, BigUint::from(small.first));
+        assert_eq!(big.period, BigUint::from(small.period));
+        let buses = [Bus { id: 4, offset: 1 }, Bus { id: 6, offset: 0 }];
+        assert_eq!(align_big(&buses), Err(AlignError::Incompatible));
+    }
+
+    #[test]
+    fn test_next_departures() {
+        let input = parse(EXAMPLE1).unwrap();
+        let result = next_departures(&input.schedule, 939);
+        assert_eq!(
+            result,
+            [
+                Departure { id: 59, time: 944 },
+                Departure { id: 7, time: 945 },
+                Departure { id: 13, time: 949 },
+                Departure { id: 19, time: 950 },
+                Departure { id: 31, time: 961 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_align_subset() {
+        let buses = [Bus { id: 7, offset: 0 }, Bus { id: 13, offset: 1 }];
+        let alignment = align(&buses).unwrap();
+        assert_eq!(
+            alignment,
+            Alignment {
+                first: 77,
+                period: 91
+            }
+        );
+        let result = alignment.times_from(1_000).take(3).collect::<Vec<_>>();
+        assert_eq!(result, [1_078, 1_169, 1_260]);
+        let result = alignment.times_from(77).next();
+        assert_eq!(result, Some(77));
+    }
+
+    #[test]
+    fn test_align_shared_factor() {
+        let buses = [Bus { id: 4, offset: 1 }, Bus { id: 6, offset: 0 }];
+        assert_eq!(align(&buses), Err(AlignError::Incompatible));
+        let buses = [Bus { id: 4, offset: 2 }, Bus { id: 6, offset: 0 }];
+        assert_eq!(
+            align(&buses),
+            Ok(Alignment {
+                first: 6,
+                period: 12
+            })
+        );
     }
 }
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fmt::{Display, Write};
 use std::num::ParseIntError;
 use std::str::FromStr;
@@ -96,19 +96,10 @@ fn parse(input: &str) -> Result<Vec<Instruction>, ParseError> {
 }
 
 #[aoc(day14, part1)]
-fn part_1(instructions: &[Instruction]) -> u64 {
-    let mut memory = HashMap::new();
-    let mut current_mask = [Mask::Zero; 36];
-    for instr in instructions {
-        match *instr {
-            Instruction::Mask(new_mask) => current_mask = new_mask,
-            Instruction::Memory(addr, value) => {
-                let real_value = apply_mask(value, &current_mask);
-                memory.insert(addr, real_value);
-            }
-        }
-    }
-    memory.into_values().sum()
+fn part_1(instructions: &[Instruction]) -> Option<u64> {
+    let mut computer = DockingComputer::new(instructions, DecoderVersion::V1);
+    computer.run();
+    u64::try_from(computer.memory.sum()).ok()
 }
 
 fn apply_mask(mut addr: u64, mask: &[Mask]) -> u64 {
@@ -123,73 +114,249 @@ fn apply_mask(mut addr: u64, mask: &[Mask]) -> u64 {
 }
 
 #[aoc(day14, part2)]
-fn part_2(instructions: &[Instruction]) -> u64 {
-    let mut memory = HashMap::new();
-    let mut current_mask = [Mask::Zero; 36];
-
-    for instr in instructions {
-        match *instr {
-            Instruction::Mask(new_mask) => current_mask = new_mask,
-            Instruction::Memory(addr, value) => {
-                for real_addr in MaskIterator::new(&current_mask, addr) {
-                    memory.insert(real_addr, value);
+fn part_2(instructions: &[Instruction]) -> u128 {
+    let mut computer = DockingComputer::new(instructions, DecoderVersion::V2);
+    computer.run();
+    computer.memory.sum()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecoderVersion {
+    // The mask applies to the values written
+    V1,
+    // The mask applies to the addresses, with floating bits
+    V2,
+}
+
+// V1 only ever writes single addresses, so it gets a plain map.
+#[derive(Debug, Clone)]
+enum Memory {
+    Direct(BTreeMap<u64, u64>),
+    Floating(FloatingMemory),
+}
+
+impl Memory {
+    // A plain map expands floating patterns address by address.
+    fn write(&mut self, pattern: AddressPattern, value: u64) {
+        match self {
+            Self::Direct(memory) => {
+                for addr in pattern.addresses() {
+                    memory.insert(addr, value);
                 }
             }
+            Self::Floating(memory) => memory.write(pattern, value),
+        }
+    }
+
+    fn sum(&self) -> u128 {
+        match self {
+            Self::Direct(memory) => memory.values().copied().map(u128::from).sum(),
+            Self::Floating(memory) => memory.sum(),
+        }
+    }
+
+    #[allow(unused)]
+    fn read(&self, addr: u64) -> Option<u64> {
+        match self {
+            Self::Direct(memory) => memory.get(&addr).copied(),
+            Self::Floating(memory) => memory.read(addr),
+        }
+    }
+
+    // Every written address and its value, ordered by address.
+    #[allow(unused)]
+    fn dump(&self) -> Vec<(u64, u64)> {
+        match self {
+            Self::Direct(memory) => memory.iter().map(|(&addr, &value)| (addr, value)).collect(),
+            Self::Floating(memory) => memory.dump(),
         }
     }
-    memory.into_values().sum()
 }
 
-struct MaskIterator<'a> {
-    mask: &'a [Mask],
-    addr: u64,
-    started: bool,
+#[derive(Debug, Clone)]
+struct DockingComputer<'a> {
+    program: &'a [Instruction],
+    pc: usize,
+    mask: [Mask; 36],
+    version: DecoderVersion,
+    memory: Memory,
 }
 
-impl<'a> MaskIterator<'a> {
-    const fn new(mask: &'a [Mask], addr: u64) -> Self {
+impl<'a> DockingComputer<'a> {
+    fn new(program: &'a [Instruction], version: DecoderVersion) -> Self {
         Self {
-            mask,
-            addr,
-            started: false,
+            program,
+            pc: 0,
+            mask: [Mask::Zero; 36],
+            version,
+            memory: match version {
+                DecoderVersion::V1 => Memory::Direct(BTreeMap::new()),
+                DecoderVersion::V2 => Memory::Floating(FloatingMemory::default()),
+            },
         }
     }
+
+    // Executes the next instruction, and returns it.
+    fn step(&mut self) -> Option<Instruction> {
+        let instr = *self.program.get(self.pc)?;
+        self.pc += 1;
+        match (instr, self.version) {
+            (Instruction::Mask(new_mask), _) => self.mask = new_mask,
+            (Instruction::Memory(addr, value), DecoderVersion::V1) => {
+                let value = apply_mask(value, &self.mask);
+                self.memory.write(AddressPattern::single(addr), value);
+            }
+            (Instruction::Memory(addr, value), DecoderVersion::V2) => {
+                let pattern = AddressPattern::new(&self.mask, addr);
+                self.memory.write(pattern, value);
+            }
+        }
+        Some(instr)
+    }
+
+    fn run(&mut self) {
+        while self.step().is_some() {}
+    }
 }
 
-impl Iterator for MaskIterator<'_> {
-    type Item = u64;
+// A set of addresses, where the bits in `fixed` have the values in `value`,
+// and every other bit can be either 0 or 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AddressPattern {
+    fixed: u64,
+    value: u64,
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if !self.started {
-            for (shift, &mask_ch) in self.mask.iter().rev().enumerate() {
-                match mask_ch {
-                    Mask::One => {
-                        self.addr |= 1 << shift;
-                    }
-                    Mask::X => {
-                        self.addr &= !(1 << shift);
-                    }
-                    Mask::Zero => ()
-                }
+impl AddressPattern {
+    fn new(mask: &[Mask], mut addr: u64) -> Self {
+        let mut floating = 0;
+        for (shift, &mask_ch) in mask.iter().rev().enumerate() {
+            match mask_ch {
+                Mask::One => addr |= 1 << shift,
+                Mask::X => floating |= 1 << shift,
+                Mask::Zero => (),
             }
-            self.started = true;
-            return Some(self.addr);
         }
-        for (shift, &mask_ch) in self.mask.iter().rev().enumerate() {
-            if mask_ch == Mask::X {
-                if (self.addr & (1 << shift)) == 0 {
-                    self.addr |= 1 << shift;
-                    return Some(self.addr);
-                }
-                self.addr &= !(1 << shift);
-            }
+        Self {
+            fixed: !floating,
+            value: addr & !floating,
+        }
+    }
+
+    const fn single(addr: u64) -> Self {
+        Self {
+            fixed: u64::MAX,
+            value: addr,
+        }
+    }
+
+    const fn len(self) -> u128 {
+        1 << (!self.fixed).count_ones()
+    }
+
+    #[allow(unused)]
+    const fn contains(self, addr: u64) -> bool {
+        (self.value ^ addr) & self.fixed == 0
+    }
+
+    // Every address in the pattern, in increasing order.
+    fn addresses(self) -> impl Iterator<Item = u64> {
+        let floating = !self.fixed;
+        // Steps through all subsets of the floating bits, in increasing order
+        std::iter::successors(Some(0u64), move |&subset| {
+            let next = subset.wrapping_sub(floating) & floating;
+            (next != 0).then_some(next)
+        })
+        .map(move |subset| self.value | subset)
+    }
+
+    const fn intersects(self, other: Self) -> bool {
+        (self.value ^ other.value) & self.fixed & other.fixed == 0
+    }
+
+    // Splits off the parts of `self` that are not in `other`, as disjoint
+    // patterns. Each bit that is floating here but fixed in `other` splits off
+    // the half that disagrees with `other`, and keeps narrowing down the rest.
+    fn subtract(self, other: Self) -> Vec<Self> {
+        if !self.intersects(other) {
+            return vec![self];
         }
-        None
+        let mut pieces = Vec::new();
+        let mut rest = self;
+        let mut split = other.fixed & !self.fixed;
+        while split != 0 {
+            let bit = split & split.wrapping_neg();
+            split &= !bit;
+            rest.fixed |= bit;
+            pieces.push(Self {
+                fixed: rest.fixed,
+                value: rest.value | (!other.value & bit),
+            });
+            rest.value |= other.value & bit;
+        }
+        pieces
+    }
+}
+
+// Memory written through floating addresses, stored as disjoint address
+// patterns, without expanding them into single addresses.
+#[derive(Debug, Clone, Default)]
+struct FloatingMemory {
+    regions: Vec<(AddressPattern, u64)>,
+}
+
+impl FloatingMemory {
+    fn write(&mut self, pattern: AddressPattern, value: u64) {
+        let mut pieces = Vec::new();
+        self.regions.retain(|&(region, old)| {
+            if !region.intersects(pattern) {
+                return true;
+            }
+            pieces.extend(
+                region
+                    .subtract(pattern)
+                    .into_iter()
+                    .map(|piece| (piece, old)),
+            );
+            false
+        });
+        self.regions.extend(pieces);
+        self.regions.push((pattern, value));
+    }
+
+    #[allow(unused)]
+    fn read(&self, addr: u64) -> Option<u64> {
+        self.regions
+            .iter()
+            .find(|(region, _)| region.contains(addr))
+            .map(|&(_, value)| value)
+    }
+
+    // Every written address and its value, ordered by address. This expands
+    // the floating addresses, so it is meant for debugging small programs.
+    #[allow(unused)]
+    fn dump(&self) -> Vec<(u64, u64)> {
+        let mut cells = self
+            .regions
+            .iter()
+            .flat_map(|&(region, value)| region.addresses().map(move |addr| (addr, value)))
+            .collect::<Vec<_>>();
+        cells.sort_unstable();
+        cells
+    }
+
+    fn sum(&self) -> u128 {
+        self.regions
+            .iter()
+            .map(|&(region, value)| region.len() * u128::from(value))
+            .sum()
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use super::*;
 
     const EXAMPLE1: &str = "\
@@ -230,7 +397,7 @@ mod tests {
     fn test_part_1() {
         let instructions = parse(EXAMPLE1).unwrap();
         let result = part_1(&instructions);
-        assert_eq!(result, 165);
+        assert_eq!(result, Some(165));
     }
 
     #[test]
@@ -239,4 +406,143 @@ mod tests {
         let result = part_2(&instructions);
         assert_eq!(result, 208);
     }
+
+    fn pattern(mask: &str, addr: u64) -> AddressPattern {
+        let Ok(Instruction::Mask(mask)) = format!("mask = {mask}").parse() else {
+            panic!("Invalid mask: {mask}");
+        };
+        AddressPattern::new(&mask, addr)
+    }
+
+    fn expand(pattern: AddressPattern) -> Vec<u64> {
+        pattern.addresses().collect()
+    }
+
+    #[test]
+    fn test_subtract() {
+        let a = pattern("0000000000000000000000000000000XXX0X", 0);
+        let b = pattern("0000000000000000000000000000000X1XXX", 1);
+        let pieces = a.subtract(b);
+        let mut remaining = pieces.iter().copied().flat_map(expand).collect::<Vec<_>>();
+        remaining.sort_unstable();
+        let mut expected = expand(a)
+            .into_iter()
+            .filter(|addr| !expand(b).contains(addr))
+            .collect::<Vec<_>>();
+        expected.sort_unstable();
+        assert_eq!(remaining, expected);
+        let total = pieces.iter().map(|piece| piece.len()).sum::<u128>();
+        assert_eq!(total, u128::try_from(expected.len()).unwrap());
+    }
+
+    #[test]
+    fn test_floating_memory_matches_expansion() {
+        let instructions = parse(
+            "\
+            mask = 0000000000000000000000000000X1X0XX1X\n\
+            mem[42] = 100\n\
+            mem[7] = 3\n\
+            mask = 00000000000000000000000000000X0XXX0X\n\
+            mem[26] = 1\n\
+            mask = 000000000000000000000000000000XX1XX0\n\
+            mem[5] = 17\n\
+            mem[5] = 0\n\
+            mem[13] = 9\
+            ",
+        )
+        .unwrap();
+        let mut expanded = HashMap::new();
+        let mut current_mask = [Mask::Zero; 36];
+        for instr in &instructions {
+            match *instr {
+                Instruction::Mask(new_mask) => current_mask = new_mask,
+                Instruction::Memory(addr, value) => {
+                    for real_addr in expand(AddressPattern::new(&current_mask, addr)) {
+                        expanded.insert(real_addr, value);
+                    }
+                }
+            }
+        }
+        let expected = expanded.into_values().map(u128::from).sum::<u128>();
+        assert_eq!(part_2(&instructions), expected);
+    }
+
+    #[test]
+    fn test_addresses() {
+        let result = expand(pattern("000000000000000000000000000000X1001X", 42));
+        assert_eq!(result, [26, 27, 58, 59]);
+    }
+
+    #[test]
+    fn test_memory_layouts_agree() {
+        let mut direct = Memory::Direct(BTreeMap::new());
+        let mut floating = Memory::Floating(FloatingMemory::default());
+        for memory in [&mut direct, &mut floating] {
+            memory.write(pattern("000000000000000000000000000000X1001X", 42), 100);
+            memory.write(AddressPattern::single(26), 1);
+        }
+        assert_eq!(direct.dump(), floating.dump());
+        assert_eq!(direct.sum(), 301);
+        assert_eq!(floating.sum(), 301);
+    }
+
+    #[test]
+    fn test_step_v1() {
+        let instructions = parse(EXAMPLE1).unwrap();
+        let mut computer = DockingComputer::new(&instructions, DecoderVersion::V1);
+        assert!(matches!(computer.step(), Some(Instruction::Mask(..))));
+        assert_eq!(computer.step(), Some(Instruction::Memory(8, 11)));
+        assert_eq!(computer.memory.dump(), [(8, 73)]);
+        assert_eq!(computer.step(), Some(Instruction::Memory(7, 101)));
+        assert_eq!(computer.step(), Some(Instruction::Memory(8, 0)));
+        assert_eq!(computer.step(), None);
+        assert_eq!(computer.memory.dump(), [(7, 101), (8, 64)]);
+        assert_eq!(computer.memory.read(8), Some(64));
+        assert_eq!(computer.memory.read(9), None);
+    }
+
+    #[test]
+    fn test_dump_v2() {
+        let instructions = parse(EXAMPLE2).unwrap();
+        let mut computer = DockingComputer::new(&instructions, DecoderVersion::V2);
+        computer.step();
+        computer.step();
+        assert_eq!(
+            computer.memory.dump(),
+            [(26, 100), (27, 100), (58, 100), (59, 100)]
+        );
+        computer.run();
+        assert_eq!(
+            computer.memory.dump(),
+            [
+                (16, 1),
+                (17, 1),
+                (18, 1),
+                (19, 1),
+                (24, 1),
+                (25, 1),
+                (26, 1),
+                (27, 1),
+                (58, 100),
+                (59, 100)
+            ]
+        );
+        assert_eq!(computer.memory.read(58), Some(100));
+        assert_eq!(computer.memory.read(20), None);
+    }
+
+    #[test]
+    fn test_many_floating_bits() {
+        let instructions = parse(
+            "\
+            mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX\n\
+            mem[0] = 1\n\
+            mask = 1XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX\n\
+            mem[0] = 3\
+            ",
+        )
+        .unwrap();
+        let result = part_2(&instructions);
+        assert_eq!(result, 1 << 37);
+    }
 }
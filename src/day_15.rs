@@ -1,73 +1,194 @@
-use std::collections::HashMap;
-use std::num::{NonZeroUsize, ParseIntError};
+use std::collections::{HashMap, VecDeque};
+use std::num::{NonZeroU32, NonZeroUsize, ParseIntError};
 
 #[aoc_generator(day15)]
-fn parse(input: &str) -> Result<Vec<u32>, ParseIntError> {
+fn parse(input: &str) -> Result<Vec<u64>, ParseIntError> {
     input.split(',').map(str::parse).collect()
 }
 
 #[aoc(day15, part1)]
-fn part_1(input: &[u32]) -> u32 {
-    NumberSpeaker::new(input).nth(2020 - 1).unwrap()
+fn part_1(input: &[u64]) -> Option<u64> {
+    play(input, 2020)
 }
 
 #[aoc(day15, part2)]
-fn part_2(input: &[u32]) -> u32 {
-    NumberSpeaker::new(input).nth(30_000_000 - 1).unwrap()
+fn part_2(input: &[u64]) -> Option<u64> {
+    play(input, 30_000_000)
 }
 
-struct NumberSpeaker {
-    initial_numbers: Vec<u32>,
-    turn: usize,
-    prev_number: Option<u32>,
-    history_low: Vec<Option<NonZeroUsize>>,
-    history_high: HashMap<u32, usize>,
+// The number spoken on turn `turns`, counting from 1. Games that fit in 32-bit
+// turns use a dense table, since every number spoken after the starting ones
+// is an age, which is less than the number of turns.
+fn play(start: &[u64], turns: u64) -> Option<u64> {
+    let nth = usize::try_from(turns.checked_sub(1)?).ok()?;
+    u32::try_from(turns).map_or_else(
+        |_| NumberSpeaker::new(start, SparseHistory::default()).nth(nth),
+        |turns| NumberSpeaker::new(start, DenseHistory::new(turns)).nth(nth),
+    )
 }
 
-const LIMIT: u32 = 1_000_000;
+trait History {
+    // Records that `number` was spoken on `turn`, and returns the turn it was
+    // last spoken before that.
+    fn replace(&mut self, number: u64, turn: u64) -> Option<u64>;
+}
+
+#[derive(Debug, Clone)]
+struct DenseHistory {
+    low: Vec<Option<NonZeroU32>>,
+    high: HashMap<u64, NonZeroU32>,
+}
 
-impl NumberSpeaker {
-    fn new(initial_numbers: &[u32]) -> Self {
+impl DenseHistory {
+    fn new(turns: u32) -> Self {
         Self {
-            initial_numbers: initial_numbers.to_vec(),
-            turn: 1,
-            prev_number: None,
-            history_low: vec![None; LIMIT as usize],
-            history_high: HashMap::new(),
+            low: vec![None; turns as usize],
+            high: HashMap::new(),
         }
     }
+}
+
+impl History for DenseHistory {
+    fn replace(&mut self, number: u64, turn: u64) -> Option<u64> {
+        let turn = u32::try_from(turn)
+            .ok()
+            .and_then(NonZeroU32::new)
+            .expect("Turn out of range for a dense history");
+        let previous = match usize::try_from(number) {
+            Ok(ix) if ix < self.low.len() => self.low[ix].replace(turn),
+            _ => self.high.insert(number, turn),
+        };
+        previous.map(|turn| u64::from(turn.get()))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct SparseHistory {
+    turns: HashMap<u64, u64>,
+}
+
+impl History for SparseHistory {
+    fn replace(&mut self, number: u64, turn: u64) -> Option<u64> {
+        self.turns.insert(number, turn)
+    }
+}
 
-    fn get_history(&self, value: u32) -> Option<usize> {
-        if value < LIMIT {
-            self.history_low[value as usize].map(NonZeroUsize::get)
-        } else {
-            self.history_high.get(&value).copied()
+// Variant rule: the next number is the age since the `depth`-th previous
+// occurrence of the last number, or 0 if it was spoken fewer times than that.
+// A depth of 1 gives the standard game.
+#[allow(unused)]
+#[derive(Debug, Clone)]
+struct LookbackHistory {
+    depth: NonZeroUsize,
+    turns: HashMap<u64, VecDeque<u64>>,
+}
+
+#[allow(unused)]
+impl LookbackHistory {
+    fn new(depth: NonZeroUsize) -> Self {
+        Self {
+            depth,
+            turns: HashMap::new(),
         }
     }
+}
 
-    fn set_history(&mut self, value: u32, round: usize) {
-        if value < LIMIT {
-            self.history_low[value as usize] = NonZeroUsize::new(round);
-        } else {
-            self.history_high.insert(value, round);
+impl History for LookbackHistory {
+    fn replace(&mut self, number: u64, turn: u64) -> Option<u64> {
+        let depth = self.depth.get();
+        let turns = self.turns.entry(number).or_default();
+        let previous = (turns.len() == depth).then(|| turns[0]);
+        turns.push_back(turn);
+        if turns.len() > depth {
+            turns.pop_front();
         }
+        previous
     }
 }
 
-impl Iterator for NumberSpeaker {
-    type Item = u32;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Gap {
+    number: u64,
+    from: u64,
+    to: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Statistics {
+    turns: u64,
+    zeros: u64,
+    longest_gap: Option<Gap>,
+    first_occurrence: HashMap<u64, u64>,
+}
+
+#[allow(unused)]
+impl Statistics {
+    fn distinct(&self) -> usize {
+        self.first_occurrence.len()
+    }
+}
+
+// Collects statistics over a finite run, e.g. `speaker.take(turns)`. Turns
+// are counted from 1.
+#[allow(unused)]
+fn statistics(numbers: impl IntoIterator<Item = u64>) -> Statistics {
+    let mut stats = Statistics::default();
+    let mut last_seen = HashMap::new();
+    for number in numbers {
+        stats.turns += 1;
+        let turn = stats.turns;
+        if number == 0 {
+            stats.zeros += 1;
+        }
+        stats.first_occurrence.entry(number).or_insert(turn);
+        if let Some(from) = last_seen.insert(number, turn)
+            && stats
+                .longest_gap
+                .is_none_or(|gap| gap.to - gap.from < turn - from)
+        {
+            stats.longest_gap = Some(Gap {
+                number,
+                from,
+                to: turn,
+            });
+        }
+    }
+    stats
+}
+
+#[derive(Debug, Clone)]
+struct NumberSpeaker<H> {
+    initial_numbers: Vec<u64>,
+    turn: u64,
+    prev_number: Option<u64>,
+    history: H,
+}
+
+impl<H: History> NumberSpeaker<H> {
+    fn new(initial_numbers: &[u64], history: H) -> Self {
+        Self {
+            initial_numbers: initial_numbers.to_vec(),
+            turn: 0,
+            prev_number: None,
+            history,
+        }
+    }
+}
+
+impl<H: History> Iterator for NumberSpeaker<H> {
+    type Item = u64;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let next_number = if self.turn <= self.initial_numbers.len() {
-            self.initial_numbers[self.turn - 1]
-        } else if let Some(last_round) = self.get_history(self.prev_number.unwrap()) {
-            u32::try_from(self.turn - last_round - 1).unwrap()
-        } else {
-            0
+        let last_turn = self
+            .prev_number
+            .and_then(|prev_number| self.history.replace(prev_number, self.turn));
+        let next_number = match usize::try_from(self.turn)
+            .ok()
+            .and_then(|ix| self.initial_numbers.get(ix))
+        {
+            Some(&initial) => initial,
+            None => last_turn.map_or(0, |last_turn| self.turn - last_turn),
         };
-        if let Some(prev_number) = self.prev_number {
-            self.set_history(prev_number, self.turn - 1);
-        }
         self.turn += 1;
         self.prev_number = Some(next_number);
         Some(next_number)
@@ -86,9 +207,9 @@ mod tests {
     #[test_case("2,3,1" => 78)]
     #[test_case("3,2,1" => 438)]
     #[test_case("3,1,2" => 1836)]
-    fn test_part_1(input: &str) -> u32 {
+    fn test_part_1(input: &str) -> u64 {
         let nums = parse(input).unwrap();
-        part_1(&nums)
+        part_1(&nums).unwrap()
     }
 
     #[ignore = "slow"]
@@ -99,8 +220,78 @@ mod tests {
     #[test_case("2,3,1" => 6_895_259)]
     #[test_case("3,2,1" => 18)]
     #[test_case("3,1,2" => 362)]
-    fn test_part_2(input: &str) -> u32 {
+    fn test_part_2(input: &str) -> u64 {
         let nums = parse(input).unwrap();
-        part_2(&nums)
+        part_2(&nums).unwrap()
+    }
+
+    #[test]
+    fn test_number_speaker() {
+        let speaker = NumberSpeaker::new(&[0, 3, 6], DenseHistory::new(10));
+        let result = speaker.take(10).collect::<Vec<_>>();
+        assert_eq!(result, [0, 3, 6, 0, 3, 3, 1, 0, 4, 0]);
+    }
+
+    #[test_case(0 => None)]
+    #[test_case(1 => Some(0))]
+    #[test_case(3 => Some(6))]
+    #[test_case(10 => Some(0))]
+    fn test_play(turns: u64) -> Option<u64> {
+        play(&[0, 3, 6], turns)
+    }
+
+    #[test]
+    fn test_large_starting_numbers() {
+        let start = [5_000_000_000, 7, 5_000_000_000];
+        let result = play(&start, 4);
+        let expected = NumberSpeaker::new(&start, SparseHistory::default()).nth(3);
+        assert_eq!(result, expected);
+        assert_eq!(result, Some(2));
+    }
+
+    #[test]
+    fn test_sparse_matches_dense() {
+        let dense = NumberSpeaker::new(&[2, 1, 3], DenseHistory::new(2020));
+        let sparse = NumberSpeaker::new(&[2, 1, 3], SparseHistory::default());
+        assert!(dense.take(2020).eq(sparse.take(2020)));
+    }
+
+    #[test]
+    fn test_statistics() {
+        let speaker = NumberSpeaker::new(&[0, 3, 6], DenseHistory::new(10));
+        let stats = statistics(speaker.take(10));
+        assert_eq!(stats.turns, 10);
+        assert_eq!(stats.distinct(), 5);
+        assert_eq!(stats.zeros, 4);
+        assert_eq!(
+            stats.longest_gap,
+            Some(Gap {
+                number: 0,
+                from: 4,
+                to: 8
+            })
+        );
+        let expected = HashMap::from([(0, 1), (3, 2), (6, 3), (1, 7), (4, 9)]);
+        assert_eq!(stats.first_occurrence, expected);
+    }
+
+    #[test]
+    fn test_statistics_empty() {
+        assert_eq!(statistics([]), Statistics::default());
+    }
+
+    #[test_case(1 => vec![0, 3, 6, 0, 3, 3, 1, 0, 4, 0])]
+    #[test_case(2 => vec![0, 3, 6, 0, 0, 4, 0, 3, 0, 4])]
+    fn test_lookback(depth: usize) -> Vec<u64> {
+        let history = LookbackHistory::new(NonZeroUsize::new(depth).unwrap());
+        NumberSpeaker::new(&[0, 3, 6], history).take(10).collect()
+    }
+
+    #[test]
+    fn test_lookback_one_matches_standard() {
+        let lookback = LookbackHistory::new(NonZeroUsize::MIN);
+        let standard = NumberSpeaker::new(&[3, 1, 2], SparseHistory::default());
+        let lookback = NumberSpeaker::new(&[3, 1, 2], lookback);
+        assert!(lookback.take(2020).eq(standard.take(2020)));
     }
 }
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write};
 use std::num::ParseIntError;
 use std::str::FromStr;
 
@@ -9,23 +11,73 @@ enum ParseError {
     SyntaxError,
     #[error(transparent)]
     InvalidNumber(#[from] ParseIntError),
+    #[error("Ticket has {found} values, but there are {expected} fields")]
+    TicketLength { expected: usize, found: usize },
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum Category {
-    Departure,
-    Other,
+// Fixed-width set of field indices, with as many words as the width needs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BitSet {
+    words: Vec<u64>,
 }
 
-impl FromStr for Category {
-    type Err = ParseError;
+impl BitSet {
+    fn empty(len: usize) -> Self {
+        Self {
+            words: vec![0; len.div_ceil(64)],
+        }
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(if s.starts_with("departure ") {
-            Self::Departure
-        } else {
-            Self::Other
-        })
+    fn full(len: usize) -> Self {
+        let mut words = vec![!0; len.div_ceil(64)];
+        if let Some(last) = words.last_mut()
+            && !len.is_multiple_of(64)
+        {
+            *last = !(!0 << (len % 64));
+        }
+        Self { words }
+    }
+
+    fn insert(&mut self, ix: usize) {
+        self.words[ix / 64] |= 1 << (ix % 64);
+    }
+
+    fn contains(&self, ix: usize) -> bool {
+        self.words[ix / 64] & (1 << (ix % 64)) != 0
+    }
+
+    fn remove(&mut self, ix: usize) {
+        self.words[ix / 64] &= !(1 << (ix % 64));
+    }
+
+    fn difference(&self, other: &Self) -> Self {
+        Self {
+            words: self
+                .words
+                .iter()
+                .zip(&other.words)
+                .map(|(a, b)| a & !b)
+                .collect(),
+        }
+    }
+
+    #[allow(unused)]
+    fn first(&self) -> Option<usize> {
+        self.words
+            .iter()
+            .position(|&w| w != 0)
+            .map(|ix| ix * 64 + self.words[ix].trailing_zeros() as usize)
+    }
+
+    // The only member, if there is exactly one.
+    fn single(&self) -> Option<usize> {
+        let mut nonzero = self.words.iter().enumerate().filter(|&(_, &w)| w != 0);
+        match (nonzero.next(), nonzero.next()) {
+            (Some((ix, &word)), None) if word.is_power_of_two() => {
+                Some(ix * 64 + word.trailing_zeros() as usize)
+            }
+            _ => None,
+        }
     }
 }
 
@@ -47,6 +99,14 @@ impl ValueRange {
         self.low <= value && value <= self.high
     }
 
+    const fn distance(self, value: u16) -> u16 {
+        if value < self.low {
+            self.low - value
+        } else {
+            value.saturating_sub(self.high)
+        }
+    }
+
     fn collapse_overlapping_ranges(ranges: &mut Vec<Self>) {
         ranges.sort_unstable();
         let mut prev_opt = None::<Self>;
@@ -70,6 +130,12 @@ impl ValueRange {
     }
 }
 
+impl Display for ValueRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.low, self.high)
+    }
+}
+
 impl FromStr for ValueRange {
     type Err = ParseError;
 
@@ -82,12 +148,19 @@ impl FromStr for ValueRange {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct Field {
-    category: Category,
+    name: String,
     valid_ranges: [ValueRange; 2],
 }
 
+impl Field {
+    // The first word of the name, e.g. "departure" for "departure track".
+    fn category(&self) -> &str {
+        self.name.split(' ').next().unwrap_or_default()
+    }
+}
+
 impl FromStr for Field {
     type Err = ParseError;
 
@@ -95,7 +168,7 @@ impl FromStr for Field {
         let (label, rest) = s.split_once(": ").ok_or(ParseError::SyntaxError)?;
         let (first, second) = rest.split_once(" or ").ok_or(ParseError::SyntaxError)?;
         Ok(Self {
-            category: label.parse()?,
+            name: label.to_string(),
             valid_ranges: [first.parse()?, second.parse()?],
         })
     }
@@ -114,6 +187,18 @@ impl Ticket {
     }
 }
 
+impl Display for Ticket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (ix, value) in self.values.iter().enumerate() {
+            if ix > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{value}")?;
+        }
+        Ok(())
+    }
+}
+
 impl FromStr for Ticket {
     type Err = ParseError;
 
@@ -145,14 +230,22 @@ impl FromStr for Input {
         if lines.next().ok_or(ParseError::SyntaxError)? != "your ticket:" {
             return Err(ParseError::SyntaxError);
         }
-        let your_ticket = lines.next().ok_or(ParseError::SyntaxError)?.parse()?;
+        let your_ticket: Ticket = lines.next().ok_or(ParseError::SyntaxError)?.parse()?;
         if !lines.next().ok_or(ParseError::SyntaxError)?.is_empty() {
             return Err(ParseError::SyntaxError);
         }
         if lines.next().ok_or(ParseError::SyntaxError)? != "nearby tickets:" {
             return Err(ParseError::SyntaxError);
         }
-        let nearby_tickets = lines.map(str::parse).collect::<Result<_, _>>()?;
+        let nearby_tickets = lines.map(str::parse).collect::<Result<Vec<Ticket>, _>>()?;
+        for ticket in std::iter::once(&your_ticket).chain(&nearby_tickets) {
+            if ticket.values.len() != fields.len() {
+                return Err(ParseError::TicketLength {
+                    expected: fields.len(),
+                    found: ticket.values.len(),
+                });
+            }
+        }
 
         Ok(Self {
             fields,
@@ -162,6 +255,126 @@ impl FromStr for Input {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InvalidValue {
+    column: usize,
+    value: u16,
+    // The closest field range the value missed; on a tie, the first one in
+    // input order.
+    nearest: Option<NearestRange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NearestRange {
+    field: String,
+    range: ValueRange,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InvalidTicket {
+    index: usize,
+    values: Vec<InvalidValue>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ValidationReport {
+    invalid_tickets: Vec<InvalidTicket>,
+}
+
+impl ValidationReport {
+    fn new(input: &Input) -> Self {
+        let mut ranges = input
+            .fields
+            .iter()
+            .flat_map(|f| f.valid_ranges)
+            .collect::<Vec<_>>();
+        ValueRange::collapse_overlapping_ranges(&mut ranges);
+        let invalid_tickets = input
+            .nearby_tickets
+            .iter()
+            .enumerate()
+            .filter_map(|(index, ticket)| {
+                let values = ticket
+                    .values
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &value)| !ranges.iter().any(|r| r.contains(value)))
+                    .map(|(column, &value)| InvalidValue {
+                        column,
+                        value,
+                        nearest: input
+                            .fields
+                            .iter()
+                            .flat_map(|f| f.valid_ranges.iter().map(move |&range| (f, range)))
+                            .min_by_key(|(_, range)| range.distance(value))
+                            .map(|(f, range)| NearestRange {
+                                field: f.name.clone(),
+                                range,
+                            }),
+                    })
+                    .collect::<Vec<_>>();
+                (!values.is_empty()).then_some(InvalidTicket { index, values })
+            })
+            .collect();
+        Self { invalid_tickets }
+    }
+
+    fn error_rate(&self) -> u32 {
+        self.invalid_tickets
+            .iter()
+            .flat_map(|t| &t.values)
+            .map(|v| u32::from(v.value))
+            .sum()
+    }
+
+    fn is_invalid(&self, index: usize) -> bool {
+        self.invalid_tickets
+            .binary_search_by_key(&index, |t| t.index)
+            .is_ok()
+    }
+
+    // The nearby tickets that passed validation, in their original order.
+    fn cleaned_tickets<'a>(&self, input: &'a Input) -> Vec<&'a Ticket> {
+        input
+            .nearby_tickets
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| !self.is_invalid(index))
+            .map(|(_, ticket)| ticket)
+            .collect()
+    }
+
+    // The cleaned tickets in the same format as the puzzle input section.
+    #[allow(unused)]
+    fn export_cleaned(&self, input: &Input) -> String {
+        let mut result = String::from("nearby tickets:\n");
+        for ticket in self.cleaned_tickets(input) {
+            result += &ticket.to_string();
+            result.push('\n');
+        }
+        result
+    }
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for ticket in &self.invalid_tickets {
+            for value in &ticket.values {
+                write!(
+                    f,
+                    "nearby ticket {}: value {} in column {}",
+                    ticket.index, value.value, value.column
+                )?;
+                if let Some(nearest) = &value.nearest {
+                    write!(f, " (nearest range {} of {})", nearest.range, nearest.field)?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 #[aoc_generator(day16)]
 fn parse(input: &str) -> Result<Input, ParseError> {
     input.parse()
@@ -169,51 +382,179 @@ fn parse(input: &str) -> Result<Input, ParseError> {
 
 #[aoc(day16, part1)]
 fn part_1(input: &Input) -> u32 {
-    let mut ranges = input
-        .fields
-        .iter()
-        .flat_map(|f| f.valid_ranges)
-        .collect::<Vec<_>>();
-    ValueRange::collapse_overlapping_ranges(&mut ranges);
-    input
-        .nearby_tickets
-        .iter()
-        .map(|t| {
-            t.values
-                .iter()
-                .copied()
-                .filter(|&val| !ranges.iter().any(|r| r.contains(val)))
-                .map(u32::from)
-                .sum::<u32>()
-        })
-        .sum()
+    ValidationReport::new(input).error_rate()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+enum MappingError {
+    #[error("Column {column} does not match a single field")]
+    Unresolved { column: usize },
 }
 
 #[aoc(day16, part2)]
-fn part_2(input: &Input) -> u64 {
+fn part_2(input: &Input) -> Result<u64, MappingError> {
     let mut ranges = input
         .fields
         .iter()
         .flat_map(|f| f.valid_ranges)
         .collect::<Vec<_>>();
     ValueRange::collapse_overlapping_ranges(&mut ranges);
-    let mapping = determine_column_field_mapping(input, &ranges);
+    let mapping = determine_column_field_mapping(input, &ranges)?;
+    Ok(your_ticket_product(input, &mapping, |field| {
+        field.category() == "departure"
+    }))
+}
+
+// The product of the values on your ticket in the columns whose field
+// matches `predicate`.
+fn your_ticket_product(
+    input: &Input,
+    mapping: &[usize],
+    mut predicate: impl FnMut(&Field) -> bool,
+) -> u64 {
     input
         .your_ticket
         .values
         .iter()
-        .enumerate()
-        .filter_map(|(column_ix, &val)| {
-            let field_ix = mapping[column_ix];
-            (input.fields[field_ix].category == Category::Departure).then_some(u64::from(val))
-        })
+        .zip(mapping)
+        .filter(|&(_, &field_ix)| predicate(&input.fields[field_ix]))
+        .map(|(&val, _)| u64::from(val))
         .product()
 }
 
-fn determine_column_field_mapping(input: &Input, ranges: &[ValueRange]) -> Vec<usize> {
+type NamedTicket<'a> = BTreeMap<&'a str, u16>;
+
+// Your ticket and the valid nearby tickets, keyed by field name.
+#[allow(unused)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DecodedTickets<'a> {
+    field_names: Vec<&'a str>,
+    your_ticket: NamedTicket<'a>,
+    nearby_tickets: Vec<NamedTicket<'a>>,
+}
+
+#[allow(unused)]
+impl<'a> DecodedTickets<'a> {
+    // Fails unless every column is matched to its own field, since decoding
+    // would otherwise drop or misname values.
+    fn new(input: &'a Input) -> Result<Self, MappingError> {
+        let mut ranges = input
+            .fields
+            .iter()
+            .flat_map(|f| f.valid_ranges)
+            .collect::<Vec<_>>();
+        ValueRange::collapse_overlapping_ranges(&mut ranges);
+        let mapping = determine_column_field_mapping(input, &ranges)?;
+        let mut claimed = BitSet::empty(input.fields.len());
+        for (column, &field_ix) in mapping.iter().enumerate() {
+            if claimed.contains(field_ix) {
+                return Err(MappingError::Unresolved { column });
+            }
+            claimed.insert(field_ix);
+        }
+        let decode = |ticket: &Ticket| {
+            mapping
+                .iter()
+                .zip(&ticket.values)
+                .map(|(&field_ix, &value)| (input.fields[field_ix].name.as_str(), value))
+                .collect()
+        };
+        Ok(Self {
+            field_names: input.fields.iter().map(|f| f.name.as_str()).collect(),
+            your_ticket: decode(&input.your_ticket),
+            nearby_tickets: ValidationReport::new(input)
+                .cleaned_tickets(input)
+                .into_iter()
+                .map(decode)
+                .collect(),
+        })
+    }
+
+    // One row per ticket, with a leading `ticket` column telling yours from
+    // the nearby ones. Fields are in the order of the puzzle input.
+    fn to_csv(&self) -> String {
+        let mut result = String::from("ticket");
+        for name in &self.field_names {
+            result.push(',');
+            result += &csv_escape(name);
+        }
+        result.push('\n');
+        let rows = std::iter::once(("your", &self.your_ticket))
+            .chain(self.nearby_tickets.iter().map(|t| ("nearby", t)));
+        for (kind, ticket) in rows {
+            result += kind;
+            for name in &self.field_names {
+                result.push(',');
+                if let Some(value) = ticket.get(name) {
+                    write!(result, "{value}").unwrap();
+                }
+            }
+            result.push('\n');
+        }
+        result
+    }
+
+    fn to_json(&self) -> String {
+        let mut result = String::from("{\n  \"your ticket\": ");
+        self.write_json_ticket(&mut result, &self.your_ticket);
+        result += ",\n  \"nearby tickets\": [";
+        for (ix, ticket) in self.nearby_tickets.iter().enumerate() {
+            result += if ix == 0 { "\n    " } else { ",\n    " };
+            self.write_json_ticket(&mut result, ticket);
+        }
+        if !self.nearby_tickets.is_empty() {
+            result += "\n  ";
+        }
+        result += "]\n}\n";
+        result
+    }
+
+    fn write_json_ticket(&self, out: &mut String, ticket: &NamedTicket) {
+        out.push('{');
+        for (ix, name) in self.field_names.iter().enumerate() {
+            if ix > 0 {
+                *out += ", ";
+            }
+            match ticket.get(name) {
+                Some(value) => write!(out, "{}: {value}", json_string(name)).unwrap(),
+                None => write!(out, "{}: null", json_string(name)).unwrap(),
+            }
+        }
+        out.push('}');
+    }
+}
+
+fn csv_escape(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut result = String::from("\"");
+    for ch in text.chars() {
+        match ch {
+            '"' => result += "\\\"",
+            '\\' => result += "\\\\",
+            '\n' => result += "\\n",
+            ch if ch.is_control() => write!(result, "\\u{:04x}", u32::from(ch)).unwrap(),
+            ch => result.push(ch),
+        }
+    }
+    result.push('"');
+    result
+}
+
+// Matches each column to the one field that fits it, after the fields that
+// other columns have claimed are ruled out.
+fn determine_column_field_mapping(
+    input: &Input,
+    ranges: &[ValueRange],
+) -> Result<Vec<usize>, MappingError> {
     let n = input.fields.len();
-    let full_mask: usize = !(!0 << n);
-    let mut compatible_fields = vec![full_mask; n];
+    let mut compatible_fields = vec![BitSet::full(n); n];
     for ticket in &input.nearby_tickets {
         if !ticket.is_valid(ranges) {
             continue;
@@ -221,34 +562,33 @@ fn determine_column_field_mapping(input: &Input, ranges: &[ValueRange]) -> Vec<u
         for (column, &value) in ticket.values.iter().enumerate() {
             for (field_id, field) in input.fields.iter().enumerate() {
                 if !field.valid_ranges.iter().any(|r| r.contains(value)) {
-                    compatible_fields[column] &= !(1 << field_id);
+                    compatible_fields[column].remove(field_id);
                 }
             }
         }
     }
-    let mut locked = 0;
-    while let Some((column, field)) =
-        compatible_fields
-            .iter()
-            .enumerate()
-            .find_map(|(ix, &field_mask)| {
-                let mask = field_mask & !locked;
-                mask.is_power_of_two()
-                    .then_some((ix, mask.trailing_zeros()))
-            })
+    let mut mapping = vec![None; n];
+    let mut locked = BitSet::empty(n);
+    while let Some((column, field)) = compatible_fields
+        .iter()
+        .enumerate()
+        .filter(|&(ix, _)| mapping[ix].is_none())
+        .find_map(|(ix, fields)| fields.difference(&locked).single().map(|field| (ix, field)))
     {
-        compatible_fields[column] = 1 << field;
-        locked |= 1 << field;
-    }
-    for value in &mut compatible_fields {
-        *value = value.trailing_zeros().try_into().unwrap();
+        mapping[column] = Some(field);
+        locked.insert(field);
     }
-    compatible_fields
+    mapping
+        .into_iter()
+        .enumerate()
+        .map(|(column, field)| field.ok_or(MappingError::Unresolved { column }))
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use test_case::test_case;
 
     const EXAMPLE1: &str = "\
         class: 1-3 or 5-7\n\
@@ -286,21 +626,21 @@ mod tests {
             result.fields,
             [
                 Field {
-                    category: Category::Other,
+                    name: "class".to_string(),
                     valid_ranges: [
                         ValueRange { low: 1, high: 3 },
                         ValueRange { low: 5, high: 7 }
                     ]
                 },
                 Field {
-                    category: Category::Other,
+                    name: "row".to_string(),
                     valid_ranges: [
                         ValueRange { low: 6, high: 11 },
                         ValueRange { low: 33, high: 44 },
                     ]
                 },
                 Field {
-                    category: Category::Other,
+                    name: "seat".to_string(),
                     valid_ranges: [
                         ValueRange { low: 13, high: 40 },
                         ValueRange { low: 45, high: 50 },
@@ -359,6 +699,69 @@ mod tests {
         assert_eq!(result, 71);
     }
 
+    #[test]
+    fn test_validation_report() {
+        let input = parse(EXAMPLE1).unwrap();
+        let report = ValidationReport::new(&input);
+        assert_eq!(
+            report.invalid_tickets,
+            [
+                InvalidTicket {
+                    index: 1,
+                    values: vec![InvalidValue {
+                        column: 1,
+                        value: 4,
+                        nearest: Some(NearestRange {
+                            field: "class".to_string(),
+                            range: ValueRange { low: 1, high: 3 }
+                        }),
+                    }],
+                },
+                InvalidTicket {
+                    index: 2,
+                    values: vec![InvalidValue {
+                        column: 0,
+                        value: 55,
+                        nearest: Some(NearestRange {
+                            field: "seat".to_string(),
+                            range: ValueRange { low: 45, high: 50 }
+                        }),
+                    }],
+                },
+                InvalidTicket {
+                    index: 3,
+                    values: vec![InvalidValue {
+                        column: 2,
+                        value: 12,
+                        nearest: Some(NearestRange {
+                            field: "row".to_string(),
+                            range: ValueRange { low: 6, high: 11 }
+                        }),
+                    }],
+                },
+            ]
+        );
+        assert_eq!(
+            report.to_string(),
+            "\
+                nearby ticket 1: value 4 in column 1 (nearest range 1-3 of class)\n\
+                nearby ticket 2: value 55 in column 0 (nearest range 45-50 of seat)\n\
+                nearby ticket 3: value 12 in column 2 (nearest range 6-11 of row)\n\
+            "
+        );
+    }
+
+    #[test]
+    fn test_export_cleaned() {
+        let input = parse(EXAMPLE1).unwrap();
+        let report = ValidationReport::new(&input);
+        assert_eq!(report.export_cleaned(&input), "nearby tickets:\n7,3,47\n");
+        let input = parse(EXAMPLE2).unwrap();
+        let report = ValidationReport::new(&input);
+        assert!(report.invalid_tickets.is_empty());
+        assert_eq!(report.cleaned_tickets(&input).len(), 3);
+    }
+
     #[test]
     fn test_determine_column_field_mapping() {
         let input = parse(EXAMPLE2).unwrap();
@@ -368,14 +771,204 @@ mod tests {
             .flat_map(|f| f.valid_ranges)
             .collect::<Vec<_>>();
         ValueRange::collapse_overlapping_ranges(&mut ranges);
-        let mapping = determine_column_field_mapping(&input, &ranges);
+        let mapping = determine_column_field_mapping(&input, &ranges).unwrap();
         assert_eq!(mapping, [1, 0, 2]);
     }
 
+    #[test_case("a: 1-5 or 10-10\nb: 1-5 or 10-10\n" => MappingError::Unresolved { column: 0 }; "ambiguous")]
+    #[test_case("a: 1-1 or 9-9\nb: 2-2 or 9-9\n" => MappingError::Unresolved { column: 1 }; "claimed")]
+    fn test_unresolved_mapping(fields: &str) -> MappingError {
+        let input = parse(&format!(
+            "{fields}\nyour ticket:\n1,1\n\nnearby tickets:\n1,1"
+        ))
+        .unwrap();
+        let ranges = input
+            .fields
+            .iter()
+            .flat_map(|f| f.valid_ranges)
+            .collect::<Vec<_>>();
+        determine_column_field_mapping(&input, &ranges).unwrap_err()
+    }
+
+    #[test]
+    fn test_bit_set() {
+        let mut set = BitSet::full(130);
+        assert_eq!(set.words, [!0, !0, 0b11]);
+        assert_eq!(set.first(), Some(0));
+        assert_eq!(set.single(), None);
+        let mut other = BitSet::full(130);
+        other.remove(129);
+        assert_eq!(set.difference(&other).single(), Some(129));
+        set = BitSet::empty(130);
+        assert_eq!(set.first(), None);
+        set.insert(64);
+        assert_eq!(set.single(), Some(64));
+    }
+
+    #[test]
+    fn test_many_fields() {
+        // Field i accepts i..=n, so columns resolve one at a time from the
+        // column holding 0 upwards.
+        const N: usize = 100;
+        let mut text = String::new();
+        for field in 0..N {
+            writeln!(text, "field {field}: {field}-{N} or 500-500").unwrap();
+        }
+        let permutation = (0..N).map(|col| col * 37 % N).collect::<Vec<_>>();
+        let ticket = permutation
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        write!(
+            text,
+            "\nyour ticket:\n{ticket}\n\nnearby tickets:\n{ticket}"
+        )
+        .unwrap();
+        let input = parse(&text).unwrap();
+        let ranges = input
+            .fields
+            .iter()
+            .flat_map(|f| f.valid_ranges)
+            .collect::<Vec<_>>();
+        let mapping = determine_column_field_mapping(&input, &ranges).unwrap();
+        assert_eq!(mapping, permutation);
+    }
+
+    #[test]
+    fn test_your_ticket_product() {
+        let input = parse(EXAMPLE2).unwrap();
+        let mapping = [1, 0, 2];
+        assert_eq!(
+            your_ticket_product(&input, &mapping, |f| f.category() == "row"),
+            11
+        );
+        assert_eq!(
+            your_ticket_product(&input, &mapping, |f| f.name != "row"),
+            12 * 13
+        );
+        assert_eq!(your_ticket_product(&input, &mapping, |_| false), 1);
+    }
+
+    #[test]
+    fn test_decoded_tickets() {
+        let input = parse(EXAMPLE2).unwrap();
+        let decoded = DecodedTickets::new(&input).unwrap();
+        assert_eq!(
+            decoded.your_ticket,
+            BTreeMap::from([("class", 12), ("row", 11), ("seat", 13)])
+        );
+        assert_eq!(
+            decoded.nearby_tickets,
+            [
+                BTreeMap::from([("class", 9), ("row", 3), ("seat", 18)]),
+                BTreeMap::from([("class", 1), ("row", 15), ("seat", 5)]),
+                BTreeMap::from([("class", 14), ("row", 5), ("seat", 9)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ticket_length() {
+        let short_ticket = EXAMPLE2.replace("11,12,13", "11,12");
+        assert!(matches!(
+            parse(&short_ticket),
+            Err(ParseError::TicketLength {
+                expected: 3,
+                found: 2
+            })
+        ));
+        let long_ticket = EXAMPLE2.replace("5,14,9", "5,14,9,1");
+        assert!(matches!(
+            parse(&long_ticket),
+            Err(ParseError::TicketLength {
+                expected: 3,
+                found: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn test_export_missing_field() {
+        let decoded = DecodedTickets {
+            field_names: vec!["class", "row"],
+            your_ticket: BTreeMap::from([("row", 7)]),
+            nearby_tickets: vec![],
+        };
+        assert_eq!(decoded.to_csv(), "ticket,class,row\nyour,,7\n");
+        assert_eq!(
+            decoded.to_json(),
+            "{\n  \"your ticket\": {\"class\": null, \"row\": 7},\n  \"nearby tickets\": []\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_decoded_tickets_ambiguous() {
+        let input = parse(
+            "\
+            a: 1-5 or 10-10\n\
+            b: 1-5 or 10-10\n\
+            \n\
+            your ticket:\n\
+            2,3\n\
+            \n\
+            nearby tickets:\n\
+            5,5\
+        ",
+        )
+        .unwrap();
+        assert_eq!(
+            DecodedTickets::new(&input),
+            Err(MappingError::Unresolved { column: 0 })
+        );
+    }
+
+    #[test]
+    fn test_decoded_tickets_csv() {
+        let input = parse(EXAMPLE2).unwrap();
+        let decoded = DecodedTickets::new(&input).unwrap();
+        assert_eq!(
+            decoded.to_csv(),
+            "\
+                ticket,class,row,seat\n\
+                your,12,11,13\n\
+                nearby,9,3,18\n\
+                nearby,1,15,5\n\
+                nearby,14,5,9\n\
+            "
+        );
+    }
+
+    #[test]
+    fn test_decoded_tickets_json() {
+        let input = parse(EXAMPLE2).unwrap();
+        let decoded = DecodedTickets::new(&input).unwrap();
+        assert_eq!(
+            decoded.to_json(),
+            "\
+                {\n\
+                \x20 \"your ticket\": {\"class\": 12, \"row\": 11, \"seat\": 13},\n\
+                \x20 \"nearby tickets\": [\n\
+                \x20   {\"class\": 9, \"row\": 3, \"seat\": 18},\n\
+                \x20   {\"class\": 1, \"row\": 15, \"seat\": 5},\n\
+                \x20   {\"class\": 14, \"row\": 5, \"seat\": 9}\n\
+                \x20 ]\n\
+                }\n\
+            "
+        );
+    }
+
+    #[test]
+    fn test_escaping() {
+        assert_eq!(csv_escape("row"), "row");
+        assert_eq!(csv_escape("a,\"b\""), "\"a,\"\"b\"\"\"");
+        assert_eq!(json_string("a\"b\\c\t"), "\"a\\\"b\\\\c\\u0009\"");
+    }
+
     #[test]
     fn test_part_2() {
         let input = parse(EXAMPLE2).unwrap();
-        let result = part_2(&input);
+        let result = part_2(&input).unwrap();
         assert_eq!(result, 1);
     }
 }
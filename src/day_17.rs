@@ -1,4 +1,6 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Display, Write};
+use std::io;
 use std::ops::{Index, IndexMut};
 
 use thiserror::Error;
@@ -27,76 +29,6 @@ impl<T: Default, const D: usize> Grid<T, [usize; D]> {
             strides,
         }
     }
-
-    fn reshape<const D1: usize>(
-        &self,
-        new_size: [usize; D1],
-        offset: [usize; D1],
-    ) -> Result<Grid<T, [usize; D1]>, RehsapeError>
-    where
-        T: Clone,
-    {
-        if D1 < D {
-            return Err(RehsapeError::DimensionError);
-        }
-        let mut new_grid = Grid::new(new_size);
-        for (ix, value) in self.data.iter().enumerate() {
-            if let Some(new_ix) = self
-                .sizes
-                .iter()
-                .zip(&self.strides)
-                .map(|(&sz, &st)| ix / st % sz)
-                .chain(std::iter::repeat(0))
-                .zip(&new_grid.sizes)
-                .zip(&new_grid.strides)
-                .zip(&offset)
-                .map(|(((ix, &size), &stride), &offset)| {
-                    (ix + offset < size).then_some((ix + offset) * stride)
-                })
-                .sum::<Option<usize>>()
-            {
-                new_grid.data[new_ix] = value.clone();
-            }
-        }
-        Ok(new_grid)
-    }
-
-    fn for_each_neighbor(&self, pos: [usize; D], callback: &mut impl FnMut([usize; D], &T)) {
-        fn walk<T, const D: usize>(
-            grid: &Grid<T, [usize; D]>,
-            mut pos: [usize; D],
-            dim: usize,
-            callback: &mut impl FnMut([usize; D], &T),
-        ) {
-            if dim == D {
-                callback(pos, &grid[pos]);
-                return;
-            }
-            let low = pos[dim].saturating_sub(1);
-            let high = (pos[dim] + 1).min(grid.sizes[dim] - 1);
-            for x in low..=high {
-                pos[dim] = x;
-                walk(grid, pos, dim + 1, callback);
-            }
-        }
-        walk(self, pos, 0, callback);
-    }
-
-    fn for_each_cell(&self, callback: &mut impl FnMut([usize; D], &T)) {
-        for (ix, val) in self.data.iter().enumerate() {
-            let mut pos = [0; D];
-            for (dim, (&size, &stride)) in self.sizes.iter().zip(&self.strides).enumerate() {
-                pos[dim] = ix / stride % size;
-            }
-            callback(pos, val);
-        }
-    }
-}
-
-#[derive(Debug, Error)]
-enum RehsapeError {
-    #[error("Too few dimensions")]
-    DimensionError,
 }
 
 impl<T, const D: usize> Index<[usize; D]> for Grid<T, [usize; D]> {
@@ -143,47 +75,6 @@ where
     }
 }
 
-impl<T> Display for Grid<T, [usize; 3]>
-where
-    T: Display,
-{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let [width, height, depth] = self.sizes;
-        for y in 0..height {
-            for cell_x in 0..depth {
-                for x in 0..width {
-                    self[[x, y, cell_x]].fmt(f)?;
-                }
-                f.write_char(' ')?;
-            }
-            f.write_char('\n')?;
-        }
-        Ok(())
-    }
-}
-
-impl<T> Display for Grid<T, [usize; 4]>
-where
-    T: Display,
-{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let [width, height, depth, anakata] = self.sizes;
-        for cell_y in 0..anakata {
-            for y in 0..height {
-                for cell_x in 0..depth {
-                    for x in 0..width {
-                        self[[x, y, cell_x, cell_y]].fmt(f)?;
-                    }
-                    f.write_char(' ')?;
-                }
-                f.write_char('\n')?;
-            }
-            f.write_char('\n')?;
-        }
-        Ok(())
-    }
-}
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 enum Tile {
     #[default]
@@ -223,76 +114,246 @@ fn parse(input: &str) -> Result<Grid<Tile, [usize; 2]>, ParseError> {
     Ok(grid)
 }
 
-#[aoc(day17, part1)]
-fn part_1(grid: &Grid<Tile, [usize; 2]>) -> usize {
-    let [width, height] = grid.sizes;
-    let cycles = 6;
-    let mut grid = grid
-        .reshape(
-            [width + 2 * cycles, height + 2 * cycles, 1 + 2 * cycles],
-            [cycles, cycles, cycles],
-        )
-        .unwrap();
-    let mut next = grid.clone();
-    for _ in 0..cycles {
-        grid.for_each_cell(&mut |pos, &center| {
-            let mut count_neighbors = 0;
-            grid.for_each_neighbor(pos, &mut |npos, &neighbor| {
-                count_neighbors += u8::from(npos != pos && neighbor == Tile::Active);
-            });
-            next[pos] = match (center, count_neighbors) {
-                (Tile::Active, 2 | 3) | (Tile::Inactive, 3) => Tile::Active,
-                _ => Tile::Inactive,
+// The active cubes of a pocket dimension with any number of dimensions. Only
+// active cells are stored, so the extent only grows as far as the cubes do.
+//
+// Every dimension past the first two starts out as a single slice, so the
+// state is unchanged by negating or permuting those coordinates. Only the
+// canonical cell of each such orbit is stored: the extra coordinates as
+// ascending absolute values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PocketDimension {
+    dimensions: usize,
+    active: HashSet<Vec<i32>>,
+}
+
+#[derive(Debug, Error)]
+enum DimensionError {
+    #[error("Too few dimensions")]
+    TooFewDimensions,
+}
+
+impl PocketDimension {
+    fn new(slice: &Grid<Tile, [usize; 2]>, dimensions: usize) -> Result<Self, DimensionError> {
+        if dimensions < 2 {
+            return Err(DimensionError::TooFewDimensions);
+        }
+        let [width, height] = slice.sizes;
+        let mut active = HashSet::new();
+        for y in 0..height {
+            for x in 0..width {
+                if slice[[x, y]] == Tile::Active {
+                    let mut pos = vec![0; dimensions];
+                    pos[0] = x.try_into().unwrap();
+                    pos[1] = y.try_into().unwrap();
+                    active.insert(pos);
+                }
+            }
+        }
+        Ok(Self { dimensions, active })
+    }
+
+    fn neighbor_offsets(&self) -> Vec<Vec<i32>> {
+        let mut offsets = vec![vec![]];
+        for _ in 0..self.dimensions {
+            offsets = offsets
+                .into_iter()
+                .flat_map(|offset: Vec<i32>| {
+                    (-1..=1).map(move |d| {
+                        let mut offset = offset.clone();
+                        offset.push(d);
+                        offset
+                    })
+                })
+                .collect();
+        }
+        offsets.retain(|offset| offset.iter().any(|&d| d != 0));
+        offsets
+    }
+
+    fn canonical(mut pos: Vec<i32>) -> Vec<i32> {
+        let extra = &mut pos[2..];
+        for x in &mut *extra {
+            *x = x.abs();
+        }
+        extra.sort_unstable();
+        pos
+    }
+
+    // The number of cells in the orbit of a canonical cell.
+    fn multiplicity(pos: &[i32]) -> u64 {
+        let extra = &pos[2..];
+        let mut result = 1;
+        let mut run = 0;
+        for (ix, &x) in extra.iter().enumerate() {
+            run = if ix > 0 && extra[ix - 1] == x {
+                run + 1
+            } else {
+                1
             };
-        });
-        (grid, next) = (next, grid);
+            // Arrangements of the extra coordinates, one factor at a time.
+            result = result * (ix as u64 + 1) / run;
+            if x != 0 {
+                result *= 2;
+            }
+        }
+        result
+    }
+
+    fn step(&mut self) {
+        // Each edge between an active orbit A and an orbit N is seen once per
+        // neighbor of A's representative, so weighting by |A| counts every
+        // edge between the orbits, of which each cell of N has 1/|N|.
+        let offsets = self.neighbor_offsets();
+        let mut neighbors = HashMap::<Vec<i32>, u64>::new();
+        for pos in &self.active {
+            let weight = Self::multiplicity(pos);
+            for offset in &offsets {
+                let neighbor = pos.iter().zip(offset).map(|(x, d)| x + d).collect();
+                *neighbors.entry(Self::canonical(neighbor)).or_default() += weight;
+            }
+        }
+        self.active = neighbors
+            .into_iter()
+            .filter(|(pos, edges)| {
+                let count = edges / Self::multiplicity(pos);
+                count == 3 || (count == 2 && self.active.contains(pos))
+            })
+            .map(|(pos, _)| pos)
+            .collect();
+    }
+
+    fn run(&mut self, cycles: usize) {
+        for _ in 0..cycles {
+            self.step();
+        }
+    }
+
+    fn active_count(&self) -> u64 {
+        self.active.iter().map(|pos| Self::multiplicity(pos)).sum()
+    }
+
+    // Every active cell, with the stored orbits expanded.
+    fn cells(&self) -> HashSet<Vec<i32>> {
+        let mut cells = HashSet::new();
+        let mut pending = self.active.iter().cloned().collect::<Vec<_>>();
+        while let Some(pos) = pending.pop() {
+            if !cells.insert(pos.clone()) {
+                continue;
+            }
+            for dim in 2..self.dimensions {
+                let mut mirrored = pos.clone();
+                mirrored[dim] = -mirrored[dim];
+                pending.push(mirrored);
+                if dim + 1 < self.dimensions {
+                    let mut swapped = pos.clone();
+                    swapped.swap(dim, dim + 1);
+                    pending.push(swapped);
+                }
+            }
+        }
+        cells
+    }
+
+    // Prints the state after each cycle, the way the puzzle does.
+    #[allow(unused)]
+    fn view(&mut self, out: &mut impl io::Write, cycles: usize) -> io::Result<()> {
+        for cycle in 0..=cycles {
+            if cycle > 0 {
+                self.step();
+                write!(out, "\n\n")?;
+            }
+            match cycle {
+                0 => writeln!(out, "Before any cycles:\n")?,
+                1 => writeln!(out, "After 1 cycle:\n")?,
+                _ => writeln!(out, "After {cycle} cycles:\n")?,
+            }
+            write!(out, "{self}")?;
+        }
+        Ok(())
     }
-    let mut count_alive = 0;
-    grid.for_each_cell(&mut |_, &value| {
-        count_alive += usize::from(value == Tile::Active);
-    });
-    count_alive
 }
 
-#[aoc(day17, part2)]
-fn part_2(grid: &Grid<Tile, [usize; 2]>) -> usize {
-    let [width, height] = grid.sizes;
-    let cycles = 6;
-    let mut grid = grid
-        .reshape(
-            [
-                width + 2 * cycles,
-                height + 2 * cycles,
-                1 + 2 * cycles,
-                1 + 2 * cycles,
-            ],
-            [cycles, cycles, cycles, cycles],
-        )
-        .unwrap();
-    let mut next = grid.clone();
-    for _ in 0..cycles {
-        grid.for_each_cell(&mut |pos, &center| {
-            let mut count_neighbors = 0;
-            grid.for_each_neighbor(pos, &mut |npos, &neighbor| {
-                count_neighbors += u8::from(npos != pos && neighbor == Tile::Active);
-            });
-            next[pos] = match (center, count_neighbors) {
-                (Tile::Active, 2 | 3) | (Tile::Inactive, 3) => Tile::Active,
-                _ => Tile::Inactive,
-            };
-        });
-        (grid, next) = (next, grid);
+// Only the slices with active cells, each cropped to the bounding box of the
+// whole state and labelled with its coordinates beyond x and y.
+impl Display for PocketDimension {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const AXES: [&str; 2] = ["z", "w"];
+        let cells = self.cells();
+        let Some((min_x, max_x)) = cells.iter().map(|pos| (pos[0], pos[0])).reduce(min_max) else {
+            return Ok(());
+        };
+        let (min_y, max_y) = cells
+            .iter()
+            .map(|pos| (pos[1], pos[1]))
+            .reduce(min_max)
+            .unwrap();
+        // Outermost axis first, matching the puzzle's order.
+        let mut slices = BTreeMap::<Vec<i32>, HashSet<(i32, i32)>>::new();
+        for pos in &cells {
+            let key = pos[2..].iter().rev().copied().collect();
+            slices.entry(key).or_default().insert((pos[0], pos[1]));
+        }
+        for (ix, (key, slice)) in slices.iter().enumerate() {
+            if ix > 0 {
+                f.write_char('\n')?;
+            }
+            for (dim, coord) in key.iter().rev().enumerate() {
+                if dim > 0 {
+                    f.write_str(", ")?;
+                }
+                match AXES.get(dim) {
+                    Some(axis) => write!(f, "{axis}={coord}")?,
+                    None => write!(f, "d{}={coord}", dim + 2)?,
+                }
+            }
+            if !key.is_empty() {
+                f.write_char('\n')?;
+            }
+            for y in min_y..=max_y {
+                for x in min_x..=max_x {
+                    let tile = if slice.contains(&(x, y)) {
+                        Tile::Active
+                    } else {
+                        Tile::Inactive
+                    };
+                    tile.fmt(f)?;
+                }
+                f.write_char('\n')?;
+            }
+        }
+        Ok(())
     }
-    let mut count_alive = 0;
-    grid.for_each_cell(&mut |_, &value| {
-        count_alive += usize::from(value == Tile::Active);
-    });
-    count_alive
+}
+
+fn min_max((min1, max1): (i32, i32), (min2, max2): (i32, i32)) -> (i32, i32) {
+    (min1.min(min2), max1.max(max2))
+}
+
+fn simulate(
+    slice: &Grid<Tile, [usize; 2]>,
+    dimensions: usize,
+    cycles: usize,
+) -> Result<u64, DimensionError> {
+    let mut pocket = PocketDimension::new(slice, dimensions)?;
+    pocket.run(cycles);
+    Ok(pocket.active_count())
+}
+
+#[aoc(day17, part1)]
+fn part_1(grid: &Grid<Tile, [usize; 2]>) -> Result<u64, DimensionError> {
+    simulate(grid, 3, 6)
+}
+
+#[aoc(day17, part2)]
+fn part_2(grid: &Grid<Tile, [usize; 2]>) -> Result<u64, DimensionError> {
+    simulate(grid, 4, 6)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use test_case::test_case;
 
     const EXAMPLE: &str = "\
         .#.\n\
@@ -326,14 +387,140 @@ mod tests {
     #[test]
     fn test_part_1() {
         let grid = parse(EXAMPLE).unwrap();
-        let result = part_1(&grid);
+        let result = part_1(&grid).unwrap();
         assert_eq!(result, 112);
     }
 
     #[test]
     fn test_part_2() {
         let grid = parse(EXAMPLE).unwrap();
-        let result = part_2(&grid);
+        let result = part_2(&grid).unwrap();
         assert_eq!(result, 848);
     }
+
+    #[test_case(2, 4 => 5; "glider")]
+    #[test_case(3, 1 => 11)]
+    #[test_case(4, 1 => 29)]
+    #[test_case(3, 10 => 380)]
+    #[test_case(5, 6 => 5760)]
+    #[test_case(6, 6 => 35_936)]
+    fn test_simulate(dimensions: usize, cycles: usize) -> u64 {
+        let grid = parse(EXAMPLE).unwrap();
+        simulate(&grid, dimensions, cycles).unwrap()
+    }
+
+    #[test_case(&[1, 2, -3, 0, 2] => vec![1, 2, 0, 2, 3])]
+    #[test_case(&[-1, -2, -1] => vec![-1, -2, 1])]
+    fn test_canonical(pos: &[i32]) -> Vec<i32> {
+        PocketDimension::canonical(pos.to_vec())
+    }
+
+    #[test_case(&[5, 5] => 1)]
+    #[test_case(&[5, 5, 0, 0] => 1)]
+    #[test_case(&[5, 5, 0, 1] => 4)]
+    #[test_case(&[5, 5, 1, 1] => 4)]
+    #[test_case(&[5, 5, 0, 1, 2] => 24)]
+    #[test_case(&[5, 5, 0, 0, 1, 1] => 24)]
+    fn test_multiplicity(pos: &[i32]) -> u64 {
+        PocketDimension::multiplicity(pos)
+    }
+
+    #[test]
+    fn test_view() {
+        let grid = parse(EXAMPLE).unwrap();
+        let mut pocket = PocketDimension::new(&grid, 3).unwrap();
+        let mut out = Vec::new();
+        pocket.view(&mut out, 2).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\
+                Before any cycles:\n\
+                \n\
+                z=0\n\
+                .#.\n\
+                ..#\n\
+                ###\n\
+                \n\
+                \n\
+                After 1 cycle:\n\
+                \n\
+                z=-1\n\
+                #..\n\
+                ..#\n\
+                .#.\n\
+                \n\
+                z=0\n\
+                #.#\n\
+                .##\n\
+                .#.\n\
+                \n\
+                z=1\n\
+                #..\n\
+                ..#\n\
+                .#.\n\
+                \n\
+                \n\
+                After 2 cycles:\n\
+                \n\
+                z=-2\n\
+                .....\n\
+                .....\n\
+                ..#..\n\
+                .....\n\
+                .....\n\
+                \n\
+                z=-1\n\
+                ..#..\n\
+                .#..#\n\
+                ....#\n\
+                .#...\n\
+                .....\n\
+                \n\
+                z=0\n\
+                ##...\n\
+                ##...\n\
+                #....\n\
+                ....#\n\
+                .###.\n\
+                \n\
+                z=1\n\
+                ..#..\n\
+                .#..#\n\
+                ....#\n\
+                .#...\n\
+                .....\n\
+                \n\
+                z=2\n\
+                .....\n\
+                .....\n\
+                ..#..\n\
+                .....\n\
+                .....\n\
+            "
+        );
+    }
+
+    #[test]
+    fn test_display_4d() {
+        let grid = parse(EXAMPLE).unwrap();
+        let mut pocket = PocketDimension::new(&grid, 4).unwrap();
+        pocket.step();
+        let text = pocket.to_string();
+        assert!(text.starts_with("z=-1, w=-1\n#..\n..#\n.#.\n\nz=0, w=-1\n"));
+        assert_eq!(text.matches("z=").count(), 9);
+        assert_eq!(pocket.cells().len(), 29);
+    }
+
+    #[test]
+    fn test_display_empty() {
+        let grid = parse("...").unwrap();
+        let pocket = PocketDimension::new(&grid, 3).unwrap();
+        assert_eq!(pocket.to_string(), "");
+    }
+
+    #[test]
+    fn test_too_few_dimensions() {
+        let grid = parse(EXAMPLE).unwrap();
+        assert!(simulate(&grid, 1, 6).is_err());
+    }
 }
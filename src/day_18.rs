@@ -1,4 +1,5 @@
-use std::fmt::Debug;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
 use std::num::ParseIntError;
 use std::str::FromStr;
 
@@ -14,7 +15,7 @@ enum ParseError {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Token {
-    Number(u64),
+    Number(i64),
     Operator(Operator),
     OpenParen,
     CloseParen,
@@ -26,7 +27,10 @@ impl FromStr for Token {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         Ok(match s {
             "+" => Self::Operator(Operator::Plus),
+            "-" => Self::Operator(Operator::Minus),
             "*" => Self::Operator(Operator::Times),
+            "/" => Self::Operator(Operator::Divide),
+            "^" => Self::Operator(Operator::Power),
             "(" => Self::OpenParen,
             ")" => Self::CloseParen,
             _ if s.bytes().all(|b| b.is_ascii_digit()) => Self::Number(s.parse()?),
@@ -35,164 +39,403 @@ impl FromStr for Token {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum Operator {
     Plus,
+    Minus,
     Times,
+    Divide,
+    Power,
+}
+
+impl Operator {
+    // Checked, so overflow is an error rather than a panic or a wrapped value.
+    fn apply(self, lhs: i64, rhs: i64, at: Position) -> Result<i64, EvaluationError> {
+        let result = match self {
+            Self::Plus => lhs.checked_add(rhs),
+            Self::Minus => lhs.checked_sub(rhs),
+            Self::Times => lhs.checked_mul(rhs),
+            Self::Divide if rhs == 0 => return Err(EvaluationError::DivisionByZero(at)),
+            Self::Divide => lhs.checked_div(rhs),
+            Self::Power => {
+                let exponent =
+                    u32::try_from(rhs).map_err(|_| EvaluationError::NegativeExponent(at))?;
+                lhs.checked_pow(exponent)
+            }
+        };
+        result.ok_or(EvaluationError::Overflow(at))
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Associativity {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Binding {
+    precedence: u8,
+    associativity: Associativity,
+}
+
+// Binary operators missing from the table are rejected. Unary minus binds
+// with `negate` precedence, so `-2 ^ 2` is `-(2 ^ 2)` if `^` binds tighter.
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct Expression {
-    tokens: Vec<Token>,
+struct OperatorTable {
+    negate: u8,
+    bindings: HashMap<Operator, Binding>,
 }
 
-#[derive(Debug, Error)]
-enum EvaluationError {
-    #[error("Unexpected number {0} in expression")]
-    UnexpectedNumber(u64),
-    #[error("Unexpected operator {0:?} in expression")]
-    UnexpectedOperator(Operator),
-    #[error("Unexpected '(' in expression")]
-    UnexpectedOpenParen,
-    #[error("Unexpected ')' in expression")]
-    UnexpectedCloseParen,
-    #[error("Unclosed parens")]
-    UnclosedParens,
-    #[error("Unexpected end of expression")]
-    UnexpectedEnd,
+impl OperatorTable {
+    fn new(negate: u8) -> Self {
+        Self {
+            negate,
+            bindings: HashMap::new(),
+        }
+    }
+
+    fn with(mut self, op: Operator, precedence: u8, associativity: Associativity) -> Self {
+        self.bindings.insert(
+            op,
+            Binding {
+                precedence,
+                associativity,
+            },
+        );
+        self
+    }
+
+    // Everything from left to right.
+    fn flat() -> Self {
+        [
+            Operator::Plus,
+            Operator::Minus,
+            Operator::Times,
+            Operator::Divide,
+            Operator::Power,
+        ]
+        .into_iter()
+        .fold(Self::new(2), |table, op| {
+            table.with(op, 1, Associativity::Left)
+        })
+    }
+
+    // Addition and subtraction before multiplication and division.
+    fn addition_first() -> Self {
+        Self::new(4)
+            .with(Operator::Plus, 2, Associativity::Left)
+            .with(Operator::Minus, 2, Associativity::Left)
+            .with(Operator::Times, 1, Associativity::Left)
+            .with(Operator::Divide, 1, Associativity::Left)
+            .with(Operator::Power, 3, Associativity::Right)
+    }
+
+    #[allow(unused)]
+    fn conventional() -> Self {
+        Self::new(3)
+            .with(Operator::Plus, 1, Associativity::Left)
+            .with(Operator::Minus, 1, Associativity::Left)
+            .with(Operator::Times, 2, Associativity::Left)
+            .with(Operator::Divide, 2, Associativity::Left)
+            .with(Operator::Power, 4, Associativity::Right)
+    }
+
+    fn binding(&self, op: Operator) -> Option<Binding> {
+        self.bindings.get(&op).copied()
+    }
 }
 
-impl Expression {
-    fn evaluate<RULES>(&self) -> Result<u64, EvaluationError>
-    where
-        RULES: EvaluationRules,
-        RULES::State: Copy,
-    {
-        let mut state = None;
-        let mut operator = None;
-        let mut stack = Vec::new();
-        for &tok in &self.tokens {
-            match tok {
-                Token::Number(num) => {
-                    state = Some(match (state, operator) {
-                        (None, None) => RULES::start(num),
-                        (Some(state), Some(op)) => RULES::evaluate(state, op, num),
-                        _ => return Err(EvaluationError::UnexpectedNumber(num)),
-                    });
-                    operator = None;
+// Where in an expression something happened: the index of the token, and
+// the column it starts at. The end of the expression is one past the last
+// token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    token: usize,
+    column: usize,
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "token {}, column {}", self.token, self.column)
+    }
+}
+
+// Operators keep the position of their token, for reporting errors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Ast {
+    Number(i64),
+    Negate {
+        at: Position,
+        inner: Box<Self>,
+    },
+    Binary {
+        op: Operator,
+        at: Position,
+        lhs: Box<Self>,
+        rhs: Box<Self>,
+    },
+}
+
+impl Ast {
+    // Uses an explicit stack, since a long chain of left associative operators
+    // makes a tree as deep as the chain is long.
+    fn evaluate(&self) -> Result<i64, EvaluationError> {
+        enum Step<'a> {
+            Visit(&'a Ast),
+            Apply(&'a Ast),
+        }
+        let mut steps = vec![Step::Visit(self)];
+        let mut values = Vec::new();
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Visit(&Self::Number(num)) => values.push(num),
+                Step::Visit(node @ Self::Negate { inner, .. }) => {
+                    steps.push(Step::Apply(node));
+                    steps.push(Step::Visit(inner));
+                }
+                Step::Visit(node @ Self::Binary { lhs, rhs, .. }) => {
+                    steps.push(Step::Apply(node));
+                    steps.push(Step::Visit(rhs));
+                    steps.push(Step::Visit(lhs));
                 }
-                Token::Operator(op) => {
-                    operator = match (state, operator) {
-                        (Some(_), None) => Some(op),
-                        _ => return Err(EvaluationError::UnexpectedOperator(op)),
-                    };
+                Step::Apply(&Self::Negate { at, .. }) => {
+                    let value = values.pop().expect("Operand evaluated");
+                    values.push(value.checked_neg().ok_or(EvaluationError::Overflow(at))?);
                 }
-                Token::OpenParen => match (state, operator) {
-                    (None, None) | (Some(_), Some(_)) => {
-                        stack.push((state, operator));
-                        state = None;
-                        operator = None;
-                    }
-                    _ => return Err(EvaluationError::UnexpectedOpenParen),
-                },
-                Token::CloseParen => {
-                    let num = match (state, operator) {
-                        (Some(state), None) => RULES::finish(state),
-                        _ => return Err(EvaluationError::UnexpectedCloseParen),
-                    };
-
-                    state = Some(match stack.pop() {
-                        Some((Some(state), Some(op))) => RULES::evaluate(state, op, num),
-                        Some((None, None)) => RULES::start(num),
-                        _ => return Err(EvaluationError::UnexpectedCloseParen),
-                    });
-                    operator = None;
+                Step::Apply(&Self::Binary { op, at, .. }) => {
+                    let rhs = values.pop().expect("Operand evaluated");
+                    let lhs = values.pop().expect("Operand evaluated");
+                    values.push(op.apply(lhs, rhs, at)?);
                 }
+                Step::Apply(Self::Number(_)) => unreachable!("Numbers are never applied"),
             }
         }
-        match (stack.is_empty(), state, operator) {
-            (false, _, _) => Err(EvaluationError::UnclosedParens),
-            (_, Some(state), None) => Ok(RULES::finish(state)),
-            _ => Err(EvaluationError::UnexpectedEnd),
+        Ok(values.pop().expect("Expression evaluated"))
+    }
+
+    fn take_children(&mut self, out: &mut Vec<Self>) {
+        match self {
+            Self::Number(_) => {}
+            Self::Negate { inner, .. } => out.push(std::mem::replace(inner, Self::Number(0))),
+            Self::Binary { lhs, rhs, .. } => {
+                out.push(std::mem::replace(lhs, Self::Number(0)));
+                out.push(std::mem::replace(rhs, Self::Number(0)));
+            }
         }
     }
 }
 
-impl FromStr for Expression {
-    type Err = ParseError;
+// Dropped one node at a time, for the same reason as `evaluate`.
+impl Drop for Ast {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        self.take_children(&mut pending);
+        while let Some(mut node) = pending.pop() {
+            node.take_children(&mut pending);
+        }
+    }
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let tokens = s
-            .split_inclusive(['+', '*', '(', ')'])
-            .flat_map(|word| {
-                let n = word.len();
-                let (prefix, op) = word.split_at(n - 1);
-                if op.ends_with(['+', '*', '(', ')']) {
-                    let prefix = prefix.trim_ascii();
-                    if prefix.is_empty() {
-                        [None, Some(op.parse())]
-                    } else {
-                        [Some(prefix.parse()), Some(op.parse())]
-                    }
-                } else {
-                    let prefix = word.trim_ascii();
-                    if prefix.is_empty() {
-                        [None, None]
-                    } else {
-                        [Some(prefix.parse()), None]
-                    }
+// Parentheses, unary minus and right associative operators each nest one
+// level deeper, so the nesting is capped to keep the parser off the end of
+// the stack.
+const MAX_DEPTH: usize = 256;
+
+// Precedence climbing over a token list.
+struct Parser<'a> {
+    expression: &'a Expression,
+    pos: usize,
+    depth: usize,
+    table: &'a OperatorTable,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<(Token, Position)> {
+        let tok = self.expression.tokens.get(self.pos).copied();
+        let at = self.expression.position(self.pos);
+        self.pos += 1;
+        tok.map(|tok| (tok, at))
+    }
+
+    // Takes a wider type than the table, so that a left associative operator
+    // at the highest precedence still has a level above it.
+    fn expression(&mut self, min_precedence: u16) -> Result<Ast, EvaluationError> {
+        if self.depth == MAX_DEPTH {
+            return Err(EvaluationError::TooDeep(self.expression.position(self.pos)));
+        }
+        self.depth += 1;
+        let mut lhs = self.operand()?;
+        while let Some(&Token::Operator(op)) = self.expression.tokens.get(self.pos) {
+            let at = self.expression.position(self.pos);
+            let binding = self
+                .table
+                .binding(op)
+                .ok_or(EvaluationError::UnsupportedOperator { op, at })?;
+            let precedence = u16::from(binding.precedence);
+            if precedence < min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.expression(match binding.associativity {
+                Associativity::Left => precedence + 1,
+                Associativity::Right => precedence,
+            })?;
+            lhs = Ast::Binary {
+                op,
+                at,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            };
+        }
+        self.depth -= 1;
+        Ok(lhs)
+    }
+
+    fn operand(&mut self) -> Result<Ast, EvaluationError> {
+        match self.next() {
+            Some((Token::Number(num), _)) => Ok(Ast::Number(num)),
+            Some((Token::Operator(Operator::Minus), at)) => Ok(Ast::Negate {
+                at,
+                inner: Box::new(self.expression(self.table.negate.into())?),
+            }),
+            Some((Token::OpenParen, open)) => {
+                let inner = self.expression(0)?;
+                match self.next() {
+                    Some((Token::CloseParen, _)) => Ok(inner),
+                    Some((tok, at)) => Err(EvaluationError::unexpected(tok, at)),
+                    None => Err(EvaluationError::UnclosedParens(open)),
                 }
-            })
-            .flatten()
-            .collect::<Result<_, _>>()?;
-        Ok(Self { tokens })
+            }
+            Some((tok, at)) => Err(EvaluationError::unexpected(tok, at)),
+            None => Err(EvaluationError::UnexpectedEnd(
+                self.expression.position(self.expression.tokens.len()),
+            )),
+        }
     }
 }
 
-trait EvaluationRules {
-    type State;
-    fn start(val: u64) -> Self::State;
-    fn evaluate(state: Self::State, op: Operator, val: u64) -> Self::State;
-    fn finish(state: Self::State) -> u64;
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Expression {
+    source: String,
+    tokens: Vec<Token>,
+    // The starting column of each token.
+    columns: Vec<usize>,
 }
 
-struct FlatRules;
-impl EvaluationRules for FlatRules {
-    type State = u64;
+#[derive(Debug, Error)]
+enum EvaluationError {
+    #[error("Unexpected number {value} at {at}")]
+    UnexpectedNumber { value: i64, at: Position },
+    #[error("Unexpected operator {op:?} at {at}")]
+    UnexpectedOperator { op: Operator, at: Position },
+    #[error("Unexpected '(' at {0}")]
+    UnexpectedOpenParen(Position),
+    #[error("Unexpected ')' at {0}")]
+    UnexpectedCloseParen(Position),
+    #[error("Unclosed parens opened at {0}")]
+    UnclosedParens(Position),
+    #[error("Unexpected end of expression at {0}")]
+    UnexpectedEnd(Position),
+    #[error("Operator {op:?} at {at} is not in the precedence table")]
+    UnsupportedOperator { op: Operator, at: Position },
+    #[error("Division by zero at {0}")]
+    DivisionByZero(Position),
+    #[error("Negative exponent at {0}")]
+    NegativeExponent(Position),
+    #[error("Overflow at {0}")]
+    Overflow(Position),
+    #[error("Expression nested too deeply at {0}")]
+    TooDeep(Position),
+}
 
-    fn start(val: u64) -> Self::State {
-        val
+impl EvaluationError {
+    const fn unexpected(tok: Token, at: Position) -> Self {
+        match tok {
+            Token::Number(value) => Self::UnexpectedNumber { value, at },
+            Token::Operator(op) => Self::UnexpectedOperator { op, at },
+            Token::OpenParen => Self::UnexpectedOpenParen(at),
+            Token::CloseParen => Self::UnexpectedCloseParen(at),
+        }
     }
 
-    fn evaluate(state: Self::State, op: Operator, val: u64) -> Self::State {
-        match op {
-            Operator::Plus => state + val,
-            Operator::Times => state * val,
+    const fn position(&self) -> Position {
+        match self {
+            Self::UnexpectedNumber { at, .. }
+            | Self::UnexpectedOperator { at, .. }
+            | Self::UnsupportedOperator { at, .. }
+            | Self::UnexpectedOpenParen(at)
+            | Self::UnexpectedCloseParen(at)
+            | Self::UnclosedParens(at)
+            | Self::UnexpectedEnd(at)
+            | Self::DivisionByZero(at)
+            | Self::NegativeExponent(at)
+            | Self::Overflow(at)
+            | Self::TooDeep(at) => *at,
         }
     }
+}
 
-    fn finish(state: Self::State) -> u64 {
-        state
+impl Expression {
+    fn position(&self, token: usize) -> Position {
+        let column = self
+            .columns
+            .get(token)
+            .copied()
+            .unwrap_or_else(|| self.source.chars().count());
+        Position { token, column }
     }
-}
 
-struct TimesBeforePlus;
-impl EvaluationRules for TimesBeforePlus {
-    type State = (u64, u64);
+    fn parse_ast(&self, table: &OperatorTable) -> Result<Ast, EvaluationError> {
+        let mut parser = Parser {
+            expression: self,
+            pos: 0,
+            depth: 0,
+            table,
+        };
+        let ast = parser.expression(0)?;
+        parser.next().map_or(Ok(ast), |(tok, at)| {
+            Err(EvaluationError::unexpected(tok, at))
+        })
+    }
 
-    fn start(val: u64) -> Self::State {
-        (val, 1)
+    fn evaluate(&self, table: &OperatorTable) -> Result<i64, EvaluationError> {
+        self.parse_ast(table)?.evaluate()
     }
 
-    fn evaluate((sum, product): Self::State, op: Operator, val: u64) -> Self::State {
-        match op {
-            Operator::Plus => (sum + val, product),
-            Operator::Times => (val, sum * product),
-        }
+    // The expression, with the position of the error marked below it.
+    #[allow(unused)]
+    fn explain(&self, error: &EvaluationError) -> String {
+        let column = error.position().column;
+        format!("{}\n{:column$}^ {error}", self.source, "")
     }
+}
 
-    fn finish((sum, product): Self::State) -> u64 {
-        sum * product
+impl FromStr for Expression {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = Vec::new();
+        let mut columns = Vec::new();
+        let mut chars = s.char_indices().enumerate().peekable();
+        while let Some((column, (start, ch))) = chars.next() {
+            if ch.is_ascii_whitespace() {
+                continue;
+            }
+            let mut end = start + ch.len_utf8();
+            if ch.is_ascii_digit() {
+                while let Some((_, (ix, _))) = chars.next_if(|(_, (_, ch))| ch.is_ascii_digit()) {
+                    end = ix + 1;
+                }
+            }
+            tokens.push(s[start..end].parse()?);
+            columns.push(column);
+        }
+        Ok(Self {
+            source: s.to_string(),
+            tokens,
+            columns,
+        })
     }
 }
 
@@ -202,21 +445,15 @@ fn parse(input: &str) -> Result<Vec<Expression>, ParseError> {
 }
 
 #[aoc(day18, part1)]
-fn part_1(expressions: &[Expression]) -> u64 {
-    expressions
-        .iter()
-        .map(Expression::evaluate::<FlatRules>)
-        .sum::<Result<u64, EvaluationError>>()
-        .unwrap()
+fn part_1(expressions: &[Expression]) -> Result<i64, EvaluationError> {
+    let table = OperatorTable::flat();
+    expressions.iter().map(|expr| expr.evaluate(&table)).sum()
 }
 
 #[aoc(day18, part2)]
-fn part_2(expressions: &[Expression]) -> u64 {
-    expressions
-        .iter()
-        .map(Expression::evaluate::<TimesBeforePlus>)
-        .sum::<Result<u64, EvaluationError>>()
-        .unwrap()
+fn part_2(expressions: &[Expression]) -> Result<i64, EvaluationError> {
+    let table = OperatorTable::addition_first();
+    expressions.iter().map(|expr| expr.evaluate(&table)).sum()
 }
 
 #[cfg(test)]
@@ -256,9 +493,9 @@ mod tests {
     #[test_case(EXAMPLE4 => 437)]
     #[test_case(EXAMPLE5 => 12_240)]
     #[test_case(EXAMPLE6 => 13_632)]
-    fn test_part_1(input: &str) -> u64 {
+    fn test_part_1(input: &str) -> i64 {
         let tokens = parse(input).unwrap();
-        part_1(&tokens)
+        part_1(&tokens).unwrap()
     }
 
     #[test_case(EXAMPLE1 => 231)]
@@ -267,8 +504,197 @@ mod tests {
     #[test_case(EXAMPLE4 => 1_445)]
     #[test_case(EXAMPLE5 => 669_060)]
     #[test_case(EXAMPLE6 => 23_340)]
-    fn test_part_2(input: &str) -> u64 {
+    fn test_part_2(input: &str) -> i64 {
         let tokens = parse(input).unwrap();
-        part_2(&tokens)
+        part_2(&tokens).unwrap()
+    }
+
+    fn evaluate(input: &str, table: &OperatorTable) -> Result<i64, EvaluationError> {
+        input.parse::<Expression>().unwrap().evaluate(table)
+    }
+
+    #[test_case("1 + 2 * 3" => 7)]
+    #[test_case("2 - 3 - 4" => -5)]
+    #[test_case("2 ^ 3 ^ 2" => 512)]
+    #[test_case("-2 ^ 2" => -4)]
+    #[test_case("7 / 2 * 2" => 6)]
+    #[test_case("-(1 + 2) * -3" => 9)]
+    #[test_case("2 * -3 + 1" => -5)]
+    #[test_case("--4" => 4)]
+    fn test_conventional(input: &str) -> i64 {
+        evaluate(input, &OperatorTable::conventional()).unwrap()
+    }
+
+    #[test_case("1 + 2 * 3" => 9)]
+    #[test_case("2 ^ 3 ^ 2" => 64)]
+    #[test_case("-2 ^ 2" => 4)]
+    #[test_case("10 - 4 / 2" => 3)]
+    fn test_flat(input: &str) -> i64 {
+        evaluate(input, &OperatorTable::flat()).unwrap()
+    }
+
+    #[test]
+    fn test_highest_precedence() {
+        let table = OperatorTable::new(u8::MAX)
+            .with(Operator::Minus, u8::MAX, Associativity::Left)
+            .with(Operator::Times, 0, Associativity::Left);
+        assert_eq!(evaluate("10 - 4 - 3 * 2", &table).unwrap(), 6);
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let table = OperatorTable::conventional();
+        let shallow = format!("{}1{}", "(".repeat(200), ")".repeat(200));
+        assert_eq!(evaluate(&shallow, &table).unwrap(), 1);
+        let negated = format!("{}1", "-".repeat(200));
+        assert_eq!(evaluate(&negated, &table).unwrap(), 1);
+        let deep = format!("{}1{}", "(".repeat(200_000), ")".repeat(200_000));
+        assert!(matches!(
+            evaluate(&deep, &table),
+            Err(EvaluationError::TooDeep(Position {
+                token: 256,
+                column: 256
+            }))
+        ));
+        let negated = format!("{}1", "-".repeat(200_000));
+        assert!(matches!(
+            evaluate(&negated, &table),
+            Err(EvaluationError::TooDeep(_))
+        ));
+        let powers = format!("1{}", " ^ 1".repeat(200_000));
+        assert!(matches!(
+            evaluate(&powers, &table),
+            Err(EvaluationError::TooDeep(_))
+        ));
+    }
+
+    #[test]
+    fn test_long_chain() {
+        let sum = format!("1{}", " + 1".repeat(200_000));
+        assert_eq!(
+            evaluate(&sum, &OperatorTable::conventional()).unwrap(),
+            200_001
+        );
+    }
+
+    #[test]
+    fn test_right_associative_minus() {
+        let table = OperatorTable::new(2).with(Operator::Minus, 1, Associativity::Right);
+        assert_eq!(evaluate("10 - 4 - 3", &table).unwrap(), 9);
+    }
+
+    #[test]
+    fn test_parse_columns() {
+        let expr = "12*(3 +45)".parse::<Expression>().unwrap();
+        assert_eq!(expr.columns, [0, 2, 3, 4, 6, 7, 9]);
+    }
+
+    #[test]
+    fn test_parse_ast() {
+        let expr = "-1 + 2 * 3".parse::<Expression>().unwrap();
+        let num = |n| Box::new(Ast::Number(n));
+        let at = |token, column| Position { token, column };
+        assert_eq!(
+            expr.parse_ast(&OperatorTable::conventional()).unwrap(),
+            Ast::Binary {
+                op: Operator::Plus,
+                at: at(2, 3),
+                lhs: Box::new(Ast::Negate {
+                    at: at(0, 0),
+                    inner: num(1)
+                }),
+                rhs: Box::new(Ast::Binary {
+                    op: Operator::Times,
+                    at: at(4, 7),
+                    lhs: num(2),
+                    rhs: num(3)
+                }),
+            }
+        );
+    }
+
+    #[test_case("1 +" => (2, 3); "unexpected end")]
+    #[test_case("(1 + 2" => (0, 0); "unclosed parens")]
+    #[test_case("1 + 2)" => (3, 5); "unexpected close paren")]
+    #[test_case("1 2" => (1, 2); "unexpected number")]
+    #[test_case("(1) 2" => (3, 4); "unexpected number after parens")]
+    #[test_case("* 2" => (0, 0); "unexpected operator")]
+    #[test_case("4 / (2 - 2)" => (1, 2); "division by zero")]
+    #[test_case("2 ^ -1" => (1, 2); "negative exponent")]
+    #[test_case("9223372036854775807 + 1" => (1, 20); "overflow add")]
+    #[test_case("1 + 2 ^ 63" => (3, 6); "overflow pow")]
+    #[test_case("-(-9223372036854775807 - 1)" => (0, 0); "overflow negate")]
+    #[test_case("(-9223372036854775807 - 1) / -1" => (6, 27); "overflow divide")]
+    fn test_error_position(input: &str) -> (usize, usize) {
+        let table = OperatorTable::conventional();
+        let at = evaluate(input, &table).unwrap_err().position();
+        (at.token, at.column)
+    }
+
+    #[test]
+    fn test_errors() {
+        let table = OperatorTable::conventional();
+        assert!(matches!(
+            evaluate("1 +", &table),
+            Err(EvaluationError::UnexpectedEnd(_))
+        ));
+        assert!(matches!(
+            evaluate("(1 + 2", &table),
+            Err(EvaluationError::UnclosedParens(_))
+        ));
+        assert!(matches!(
+            evaluate("1 + 2)", &table),
+            Err(EvaluationError::UnexpectedCloseParen(_))
+        ));
+        assert!(matches!(
+            evaluate("(1) 2", &table),
+            Err(EvaluationError::UnexpectedNumber { value: 2, .. })
+        ));
+        assert!(matches!(
+            evaluate("* 2", &table),
+            Err(EvaluationError::UnexpectedOperator {
+                op: Operator::Times,
+                ..
+            })
+        ));
+        assert!(matches!(
+            evaluate("4 / (2 - 2)", &table),
+            Err(EvaluationError::DivisionByZero(_))
+        ));
+        assert!(matches!(
+            evaluate("2 ^ -1", &table),
+            Err(EvaluationError::NegativeExponent(_))
+        ));
+        assert!(matches!(
+            evaluate("3 * 4611686018427387904", &table),
+            Err(EvaluationError::Overflow(_))
+        ));
+        let table = OperatorTable::new(2).with(Operator::Plus, 1, Associativity::Left);
+        assert!(matches!(
+            evaluate("1 * 2", &table),
+            Err(EvaluationError::UnsupportedOperator {
+                op: Operator::Times,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_explain() {
+        let expr = "1 + (2 * 3".parse::<Expression>().unwrap();
+        let error = expr.evaluate(&OperatorTable::flat()).unwrap_err();
+        assert_eq!(
+            expr.explain(&error),
+            "\
+                1 + (2 * 3\n\
+                \x20   ^ Unclosed parens opened at token 2, column 4\
+            "
+        );
+        let expr = "2 * 4 / 0".parse::<Expression>().unwrap();
+        let error = expr.evaluate(&OperatorTable::flat()).unwrap_err();
+        assert_eq!(
+            expr.explain(&error),
+            "2 * 4 / 0\n      ^ Division by zero at token 3, column 6"
+        );
     }
 }